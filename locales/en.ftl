@@ -2,26 +2,62 @@ app_title = Rokuon-kun
 recording_time = Recording time: { $time }
 recording = 🔴 Recording...
 add_microphone = ➕ Add Microphone
+refresh_devices = 🔄 Refresh Devices
 start_recording = 🔴 Start Recording
 stop_recording = ⏹️ Stop Recording
+gain = Gain
+device_missing = ⚠️ Saved device "{ $name }" was not found, using another device instead
+analyzing_input = 🔍 Checking input...
+sounds_like = 🔍 Sounds like: { $label }
+label_silent = silent (unplugged / muted?)
+label_hum = low hum only (ground loop?)
+label_voice_mic = voice microphone
+label_line_music = line-level music
+label_noise = hiss only
+label_unavailable = could not open the device
 delete = 🗑️ Delete
+daemon_status = Background recording: { $status }
+daemon_start = ▶️ Start
+daemon_stop = ⏹️ Stop
 device = Device: 
 
 settings_title = Settings
 audio_format_section = Audio Format Settings
 save_format = Save Format
 sample_rate = Sample Rate
+sample_format = Sample Format
+auto = Auto
 bit_depth = Bit Depth
 language = Language
 processing_section = Processing Settings
 compressor = Compressor
 threshold = Threshold
 ratio = Ratio
+event_capture_section = Event Capture Settings
+event_capture = Event Capture
+event_min_duration = Min Duration
+event_padding = Padding
+monitor_section = Monitor Output
+monitor_output = Monitor Output
+monitor_delay = Delay
+monitor_device = Output
+default_device = Default Device
+dump = ⏭️ Dump
+monitor_unavailable = ⚠️ Monitor output is unavailable: { $reason }
+metrics_section = Monitoring Settings
+metrics_endpoint = Metrics
+restart_required = applies after restart
 save_settings = 💾 Save Settings
 to_recording = 🎙️ To Recording Page
 settings_saved = Settings saved successfully!
 settings_save_failed = Failed to save settings
 
+settings_migrated = Settings file was upgraded from v{ $from } (backup: { $backup })
+settings_load_failed = Could not read the settings file, using defaults: { $error } (backup: { $backup })
+no_backup = none
+settings_unreadable = Could not read the settings file, showing defaults (saving will overwrite it): { $error }
+overrides_active = Overridden by command line / environment for this run (takes precedence over saved settings): { $keys }
+
 enabled = ✓ Enabled
 disabled = Disabled
 japanese = 日本語
@@ -2,26 +2,62 @@ app_title = 録音くん
 recording_time = 録音時間: { $time }
 recording = 🔴 録音中...
 add_microphone = ➕ マイクを追加
+refresh_devices = 🔄 デバイスを再検出
 start_recording = 🔴 録音開始
 stop_recording = ⏹️ 録音停止
+gain = ゲイン
+device_missing = ⚠️ 前回のデバイス「{ $name }」が見つからないため、代わりのデバイスを使います
+analyzing_input = 🔍 入力を確認中...
+sounds_like = 🔍 つながっていそうなもの: { $label }
+label_silent = 無音（ケーブル抜け・ミュート・未接続？）
+label_hum = 低いノイズのみ（電源ハム？）
+label_voice_mic = 声向けのマイク
+label_line_music = ライン入力（音楽など）
+label_noise = 高いノイズのみ（ヒスノイズ？）
+label_unavailable = デバイスを開けませんでした
 delete = 🗑️ 削除
+daemon_status = バックグラウンド録音: { $status }
+daemon_start = ▶️ 開始
+daemon_stop = ⏹️ 停止
 device = デバイス: 
 
 settings_title = 設定
 audio_format_section = 音声フォーマット設定
 save_format = 保存形式
 sample_rate = サンプルレート
+sample_format = サンプル形式
+auto = 自動
 bit_depth = ビット深度
 language = 言語
 processing_section = 後処理設定
 compressor = コンプレッサー
 threshold = スレッショルド
 ratio = レシオ
+event_capture_section = イベント検出設定
+event_capture = イベント検出
+event_min_duration = 最短継続時間
+event_padding = 前後の余白
+monitor_section = モニター出力設定
+monitor_output = モニター出力
+monitor_delay = ディレイ
+monitor_device = 出力先
+default_device = 既定のデバイス
+dump = ⏭️ ダンプ
+monitor_unavailable = ⚠️ モニター出力を使えません: { $reason }
+metrics_section = 監視設定
+metrics_endpoint = メトリクス
+restart_required = 再起動後に反映
 save_settings = 💾 設定を保存
 to_recording = 🎙️ 録音ページへ
 settings_saved = 設定を保存しました！
 settings_save_failed = 設定の保存に失敗しました
 
+settings_migrated = 設定ファイルを v{ $from } から新しい形式に更新しました（バックアップ: { $backup }）
+settings_load_failed = 設定ファイルを読み込めなかったため既定値を使用しています: { $error }（バックアップ: { $backup }）
+no_backup = なし
+settings_unreadable = 設定ファイルを読み込めないため既定値を表示しています（保存すると上書きされます）: { $error }
+overrides_active = コマンドライン引数・環境変数で上書き中（保存した設定より優先されます）: { $keys }
+
 enabled = ✓ 有効
 disabled = 無効
 japanese = 日本語
@@ -0,0 +1,63 @@
+//設定ファイルの置き場所を決める
+//
+// 通常はOSの設定フォルダ（Windows: %APPDATA%\rokuon-kun など）に置く。
+// `--portable` を付けて起動するか、実行ファイルと同じフォルダに portable.txt があれば
+// 実行ファイルと同じフォルダの settings.json を使う（ショートカットなどから起動しても同じ場所になる）
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+const APP_DIR_NAME: &str = "rokuon-kun";
+const SETTINGS_FILE_NAME: &str = "settings.json";
+const PORTABLE_MARKER: &str = "portable.txt";
+
+static SETTINGS_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// ポータブルモードで動いているか
+pub fn is_portable() -> bool {
+    env::args().skip(1).any(|arg| arg == "--portable")
+        || exe_dir().is_some_and(|dir| dir.join(PORTABLE_MARKER).exists())
+}
+
+/// settings.json のパス
+pub fn settings_path() -> &'static Path {
+    SETTINGS_PATH.get_or_init(|| {
+        if is_portable() {
+            return match exe_dir() {
+                Some(dir) => dir.join(SETTINGS_FILE_NAME),
+                None => PathBuf::from(SETTINGS_FILE_NAME),
+            };
+        }
+        match config_dir() {
+            Some(dir) => dir.join(APP_DIR_NAME).join(SETTINGS_FILE_NAME),
+            None => PathBuf::from(SETTINGS_FILE_NAME),
+        }
+    })
+}
+
+/// 以前のバージョンが使っていた、カレントディレクトリの settings.json
+pub fn legacy_settings_path() -> &'static Path {
+    Path::new(SETTINGS_FILE_NAME)
+}
+
+fn exe_dir() -> Option<PathBuf> {
+    env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+}
+
+fn config_dir() -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        env_path("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_path("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env_path("XDG_CONFIG_HOME").or_else(|| env_path("HOME").map(|home| home.join(".config")))
+    }
+}
+
+fn env_path(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
@@ -0,0 +1,410 @@
+//バックグラウンド録音（GUIなしで常駐し、コマンドで録音を開始・停止する）
+//
+// `rokuon-kun --daemon` で起動し、`rokuon-kun --ctl start|stop|status|dump|quit` で操作する。
+// 通信はTCPで、"<トークン> <コマンド>" の1行に1行で応答する（成功は "ok"、失敗は "error" で始まる）。
+// トークンは初回起動時に作って設定ファイルと同じフォルダに保存するので、そのファイルを読めるユーザーだけが操作できる。
+// daemon_token.txt は全てのコマンド、daemon_status_token.txt は status だけに使える（監視用に渡す）。
+// 既定では 127.0.0.1 だけで待ち受ける。スタジオのLANなどから操作するときは daemon_address を変えて、
+// トークンが盗み見られないように daemon_tls_cert / daemon_tls_key でTLSを有効にする（TLSなしでは外に出さない）。
+// タスクスケジューラ・cron・systemdなどから起動・操作すれば、ログインしていなくても予約録音ができる
+use crate::config_path;
+use crate::delay_line;
+use crate::device_config;
+use crate::metrics;
+use crate::recorder::{self, RecordOptions};
+use crate::setting_page::AppSettings;
+use crate::tls::{self, Connection};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicU64;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const CONTROL_TOKEN_FILE: &str = "daemon_token.txt";
+const STATUS_TOKEN_FILE: &str = "daemon_status_token.txt";
+
+/// トークンで使えるコマンドの範囲
+#[derive(Clone, Copy, PartialEq)]
+enum Permission {
+    Status,
+    Control,
+}
+
+/// 1行のコマンドの最大長と、コマンドを送ってこない接続を待つ時間
+const MAX_LINE_LENGTH: u64 = 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// startの応答を返すまでに、録音が始まったかどうかを待つ時間
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct ActiveRecording {
+    device_name: String,
+    stop_flag: Arc<Mutex<bool>>,
+    handle: thread::JoinHandle<Result<(), String>>,
+}
+
+/// コマンドを待ち受けて録音を制御する。quitを受けるまで戻らない
+pub fn run(settings: &AppSettings) -> std::io::Result<()> {
+    let address = daemon_address(settings)?;
+    let tls_config = if settings.daemon_tls_cert.is_empty() {
+        // 暗号化しない通信はトークンを盗み見られるので、このPCの中だけにする
+        if !address.is_loopback() {
+            return Err(std::io::Error::other(format!(
+                "{} で待ち受けるには daemon_tls_cert / daemon_tls_key でTLSを有効にしてください",
+                address
+            )));
+        }
+        None
+    } else {
+        Some(tls::server_config(
+            Path::new(&settings.daemon_tls_cert),
+            Path::new(&settings.daemon_tls_key),
+        )?)
+    };
+    let control_token = load_or_create_token(CONTROL_TOKEN_FILE)?;
+    let status_token = load_or_create_token(STATUS_TOKEN_FILE)?;
+    let listener = TcpListener::bind((address, settings.daemon_port))?;
+    let mut recordings: Vec<ActiveRecording> = Vec::new();
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("制御接続エラー: {}", e);
+                continue;
+            }
+        };
+
+        // 何も送ってこない接続で他のコマンドが止まらないようにする
+        if let Err(e) = stream.set_read_timeout(Some(READ_TIMEOUT)) {
+            eprintln!("制御接続エラー: {}", e);
+            continue;
+        }
+        let mut connection: Box<dyn Connection> = match &tls_config {
+            Some(config) => match tls::accept(config, stream) {
+                Ok(connection) => connection,
+                Err(e) => {
+                    eprintln!("制御接続エラー: {}", e);
+                    continue;
+                }
+            },
+            None => Box::new(stream),
+        };
+        let mut line = String::new();
+        // TLSのハンドシェイクもここで行う
+        if let Err(e) = BufReader::new(Read::take(&mut connection, MAX_LINE_LENGTH)).read_line(&mut line) {
+            eprintln!("コマンド受信エラー: {}", e);
+            continue;
+        }
+
+        let (request_token, command) = line.trim().split_once(' ').unwrap_or(("", line.trim()));
+        let permission = if tokens_match(request_token, &control_token) {
+            Some(Permission::Control)
+        } else if tokens_match(request_token, &status_token) {
+            Some(Permission::Status)
+        } else {
+            None
+        };
+
+        let response = match permission {
+            None => "error unauthorized".to_string(),
+            Some(Permission::Status) if command != "status" => "error forbidden".to_string(),
+            Some(_) => match command {
+                "start" => start(&mut recordings),
+                "stop" => stop(&mut recordings),
+                "status" => status(&recordings),
+                "dump" => {
+                    delay_line::dump_all();
+                    "ok dumped".to_string()
+                }
+                "quit" => {
+                    stop(&mut recordings);
+                    "ok quit".to_string()
+                }
+                _ => format!("error unknown command: {}", command),
+            },
+        };
+
+        if let Err(e) = writeln!(connection, "{}", response).and_then(|_| connection.flush()) {
+            eprintln!("応答送信エラー: {}", e);
+        }
+        if permission == Some(Permission::Control) && command == "quit" {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Windowsでは `--daemon` / `--ctl` の出力を、起動したコンソールに出す
+/// （GUIアプリとしてビルドしているので、そのままではどこにも表示されない）
+pub fn attach_console() {
+    #[cfg(windows)]
+    unsafe {
+        use windows_sys::Win32::System::Console::{ATTACH_PARENT_PROCESS, AttachConsole};
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+/// 常駐中のプロセスにコマンドを送って、応答を返す
+pub fn send_command(settings: &AppSettings, command: &str) -> std::io::Result<String> {
+    // 操作用のトークンを読めなければ、状態確認用のトークンを使う
+    let token = fs::read_to_string(token_path(CONTROL_TOKEN_FILE))
+        .or_else(|_| fs::read_to_string(token_path(STATUS_TOKEN_FILE)))?;
+    // 全てのアドレスで待ち受けている場合は、このPCの中で接続する
+    let address = match daemon_address(settings)? {
+        IpAddr::V4(address) if address.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(address) if address.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        address => address,
+    };
+    let stream = TcpStream::connect((address, settings.daemon_port))?;
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+    let mut connection: Box<dyn Connection> = if settings.daemon_tls_cert.is_empty() {
+        Box::new(stream)
+    } else {
+        let config = tls::client_config(Path::new(&settings.daemon_tls_cert))?;
+        tls::connect(&config, address, stream)?
+    };
+    writeln!(connection, "{} {}", token.trim(), command)?;
+    connection.flush()?;
+
+    let mut response = String::new();
+    BufReader::new(connection).read_line(&mut response)?;
+    Ok(response.trim().to_string())
+}
+
+fn daemon_address(settings: &AppSettings) -> std::io::Result<IpAddr> {
+    settings.daemon_address.trim().parse().map_err(|e| {
+        std::io::Error::other(format!("daemon_address が不正です ({}): {}", settings.daemon_address, e))
+    })
+}
+
+fn token_path(file_name: &str) -> PathBuf {
+    config_path::settings_path().with_file_name(file_name)
+}
+
+// 保存されているトークンを読む。無ければ作って、自分だけが読めるファイルに保存する
+fn load_or_create_token(file_name: &str) -> std::io::Result<String> {
+    let path = token_path(file_name);
+    let saved = fs::read_to_string(&path)
+        .ok()
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty());
+    if let Some(token) = saved {
+        return Ok(token);
+    }
+
+    // OSの乱数から256bitを作る
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes)
+        .map_err(|e| std::io::Error::other(format!("トークン用の乱数を作れません: {}", e)))?;
+    let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    writeln!(options.open(&path)?, "{}", token)?;
+    Ok(token)
+}
+
+// 比べるのにかかる時間からトークンを推測されないように、全ての文字を比べる
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+// 設定に保存されているデバイス構成で録音を始める。構成が空なら既定のデバイスを使う
+fn start(recordings: &mut Vec<ActiveRecording>) -> String {
+    // 異常終了した録音が残っていたら片付けて、始め直せるようにする
+    for recording in take_finished(recordings) {
+        finish(recording);
+    }
+    if !recordings.is_empty() {
+        return "error already recording".to_string();
+    }
+
+    let settings = AppSettings::effective();
+    let input_devices = match device_config::list_input_devices() {
+        Ok(input_devices) => input_devices,
+        Err(e) => return format!("error cannot list input devices: {}", e),
+    };
+    let mut options = Vec::new();
+    let mut missing = Vec::new();
+
+    if settings.devices.is_empty() {
+        if let Some((device_name, device_index)) = device_config::default_input_device(&input_devices) {
+            options.push(RecordOptions {
+                card_index: 0,
+                device_index,
+                device_name,
+                sample_rate: settings.sample_rate,
+                sample_format: None,
+                gain_db: 0.0,
+                compressor_enabled: settings.compressor_enabled,
+            });
+        }
+    }
+    for (card_index, setup) in settings.devices.iter().enumerate() {
+        // 無人で動かすので、見つからないデバイスは別のデバイスで代用せずに飛ばす
+        match input_devices.iter().find(|(name, _)| *name == setup.device_name) {
+            Some((device_name, device_index)) => options.push(RecordOptions {
+                card_index,
+                device_index: *device_index,
+                device_name: device_name.clone(),
+                sample_rate: setup.sample_rate,
+                sample_format: device_config::parse_sample_format(&setup.sample_format),
+                gain_db: setup.gain_db,
+                compressor_enabled: setup.compressor_enabled,
+            }),
+            None => missing.push(setup.device_name.clone()),
+        }
+    }
+
+    // 見つからなかったデバイスも recording=0 としてメトリクスに出す
+    let names: Vec<String> = if settings.devices.is_empty() {
+        options.iter().map(|o| o.device_name.clone()).collect()
+    } else {
+        settings.devices.iter().map(|d| d.device_name.clone()).collect()
+    };
+    metrics::configure_devices(&names);
+
+    if options.is_empty() {
+        return "error no input device".to_string();
+    }
+
+    let mut starting = Vec::new();
+    for options in options {
+        let device_name = options.device_name.clone();
+        let stop_flag = Arc::new(Mutex::new(false));
+        let stop_flag_clone = stop_flag.clone();
+        // 録音が始まったら Ok、始められなかったら Err が届く
+        let (started_sender, started_receiver) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let started = started_sender.clone();
+            let result = recorder::record(
+                options,
+                stop_flag_clone,
+                Arc::new(Mutex::new(Vec::new())),
+                Arc::new(AtomicU64::new(0)),
+                Arc::new(Mutex::new(None)),
+                move || {
+                    let _ = started.send(Ok(()));
+                },
+            );
+            if let Err(e) = &result {
+                let _ = started_sender.send(Err(e.clone()));
+            }
+            result
+        });
+        starting.push((
+            ActiveRecording {
+                device_name,
+                stop_flag,
+                handle,
+            },
+            started_receiver,
+        ));
+    }
+
+    // 全てのデバイスが開けたか（開けなかったか）わかるまで待つ
+    let deadline = Instant::now() + START_TIMEOUT;
+    let mut failed = Vec::new();
+    for (recording, started_receiver) in starting {
+        match started_receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(Ok(())) => recordings.push(recording),
+            Ok(Err(e)) => {
+                failed.push(format!("{}: {}", recording.device_name, e));
+                let _ = recording.handle.join();
+            }
+            // まだ開いている途中のものは録音中として扱う（異常終了したらstatusでわかる）
+            Err(RecvTimeoutError::Timeout) => recordings.push(recording),
+            // 始まる前にスレッドが異常終了した
+            Err(RecvTimeoutError::Disconnected) => {
+                failed.push(format!("{}: 録音スレッドが異常終了しました", recording.device_name));
+                let _ = recording.handle.join();
+            }
+        }
+    }
+
+    if recordings.is_empty() {
+        return format!("error could not start: {}", failed.join(", "));
+    }
+    let mut response = format!("ok recording {}", device_names(recordings.iter()));
+    if !failed.is_empty() {
+        response.push_str(&format!(" (failed: {})", failed.join(", ")));
+    }
+    if !missing.is_empty() {
+        response.push_str(&format!(" (missing: {})", missing.join(", ")));
+    }
+    response
+}
+
+fn stop(recordings: &mut Vec<ActiveRecording>) -> String {
+    if recordings.is_empty() {
+        return "ok idle".to_string();
+    }
+
+    let count = recordings.len();
+    for recording in recordings.iter() {
+        *recording.stop_flag.lock().unwrap() = true;
+    }
+    for recording in recordings.drain(..) {
+        finish(recording);
+    }
+    format!("ok stopped {}", count)
+}
+
+// 録音スレッドの終了を待って、失敗していたら表示する
+fn finish(recording: ActiveRecording) {
+    match recording.handle.join() {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => eprintln!("録音エラー ({}): {}", recording.device_name, e),
+        Err(_) => eprintln!("録音スレッドが異常終了しました: {}", recording.device_name),
+    }
+}
+
+// stopを受けていないのに終わった録音を取り出す
+fn take_finished(recordings: &mut Vec<ActiveRecording>) -> Vec<ActiveRecording> {
+    let (finished, running) = recordings.drain(..).partition(|recording| recording.handle.is_finished());
+    *recordings = running;
+    finished
+}
+
+// 途中で止まった録音があれば、stop（またはstart）で片付けるまでerrorを返し続ける
+fn status(recordings: &[ActiveRecording]) -> String {
+    let (finished, running): (Vec<&ActiveRecording>, Vec<&ActiveRecording>) =
+        recordings.iter().partition(|recording| recording.handle.is_finished());
+    if finished.is_empty() {
+        if running.is_empty() {
+            "ok idle".to_string()
+        } else {
+            format!("ok recording {}", device_names(running))
+        }
+    } else {
+        let mut response = format!("error stopped unexpectedly: {}", device_names(finished));
+        if !running.is_empty() {
+            response.push_str(&format!(" (still recording: {})", device_names(running)));
+        }
+        response
+    }
+}
+
+fn device_names<'a>(recordings: impl IntoIterator<Item = &'a ActiveRecording>) -> String {
+    recordings
+        .into_iter()
+        .map(|recording| recording.device_name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
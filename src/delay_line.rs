@@ -0,0 +1,264 @@
+//モニター出力用のディレイ（放送の「ダンプ」機能つき）
+//
+// 入力をN秒遅らせてモニター/仮想出力に流す。ダンプするとためていた音声を捨てて生の音に追いつき、
+// その後は音の途切れ（無音）を最大2倍まで引き延ばしてN秒の遅延を作り直す（再生速度は変えないので音程も変わらない）。
+// 入力と出力のチャンネル数・サンプルレートが違っても、チャンネルの振り分けと線形補間で合わせる。
+// 入力と出力のクロックは少しずつずれるので、たまっている量が目標からずれた分だけ再生速度をわずかに上げ下げする
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// クロックのずれを合わせるために再生速度を変える最大の割合（0.2%、音程の変化は聞き取れない程度）。
+/// 遅延1秒分ずれるとこの割合になる
+const MAX_CORRECTION: f64 = 0.002;
+
+/// 遅延がこれ以上足りないときは、無音を引き延ばして作り直す（これより小さいずれは再生速度で合わせる）
+const REBUILD_THRESHOLD_MS: u32 = 50;
+
+/// 無音とみなすレベル（-40dBFS）と、引き延ばし始めるまでに続いている必要がある無音の長さ
+const QUIET_LEVEL: f32 = 0.01;
+const QUIET_MIN_MS: u32 = 20;
+
+/// 遅延0でも途切れないように最低限ためておく時間
+const MIN_LATENCY_MS: u32 = 50;
+
+static DUMP_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// カードの番号ごとの、モニター出力を使えなかった理由
+static MONITOR_ERRORS: Mutex<Option<HashMap<usize, String>>> = Mutex::new(None);
+
+/// 動いている全てのディレイのたまっている音声を捨てる
+pub fn dump_all() {
+    DUMP_GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// モニター出力を使えなかった理由を記録する（Noneで消す）
+pub fn set_monitor_error(card_index: usize, error: Option<String>) {
+    let mut errors = MONITOR_ERRORS.lock().unwrap();
+    let errors = errors.get_or_insert_with(HashMap::new);
+    match error {
+        Some(error) => errors.insert(card_index, error),
+        None => errors.remove(&card_index),
+    };
+}
+
+/// カードのモニター出力を使えなかった理由
+pub fn monitor_error(card_index: usize) -> Option<String> {
+    MONITOR_ERRORS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|errors| errors.get(&card_index).cloned())
+}
+
+pub struct DelayLine {
+    // 出力のチャンネル数にそろえたフレーム（入力のサンプルレートのまま）
+    buffer: VecDeque<f32>,
+    in_channels: usize,
+    out_channels: usize,
+    in_rate: f64,
+    // 出力1フレームごとに進める入力フレーム数（サンプルレートの比）
+    rate_ratio: f64,
+    target_frames: usize,
+    // 先頭フレームからの読み出し位置（小数部は補間に使う）
+    position: f64,
+    // 起動してから一度でも遅延分たまったか
+    primed: bool,
+    dump_generation: usize,
+    rebuild_threshold_frames: usize,
+    // ここからは出力のフレーム数で数える
+    quiet_min_frames: usize,
+    // 今の無音で読み進めたフレーム数と、引き延ばしたフレーム数
+    quiet_run: usize,
+    held: usize,
+    holding: bool,
+}
+
+impl DelayLine {
+    pub fn new(delay_ms: u32, in_channels: u16, in_rate: u32, out_channels: u16, out_rate: u32) -> Self {
+        let delay_ms = delay_ms.max(MIN_LATENCY_MS);
+        Self {
+            buffer: VecDeque::new(),
+            in_channels: in_channels.max(1) as usize,
+            out_channels: out_channels.max(1) as usize,
+            in_rate: in_rate as f64,
+            rate_ratio: in_rate as f64 / out_rate.max(1) as f64,
+            target_frames: (delay_ms as u64 * in_rate as u64 / 1000) as usize,
+            position: 0.0,
+            primed: false,
+            dump_generation: DUMP_GENERATION.load(Ordering::SeqCst),
+            rebuild_threshold_frames: (REBUILD_THRESHOLD_MS as u64 * in_rate as u64 / 1000) as usize,
+            quiet_min_frames: (QUIET_MIN_MS as u64 * out_rate as u64 / 1000) as usize,
+            quiet_run: 0,
+            held: 0,
+            holding: false,
+        }
+    }
+
+    // ためていた音声を捨てて、生の音に追いつく
+    fn dump(&mut self) {
+        self.buffer.clear();
+        self.position = 0.0;
+        self.quiet_run = 0;
+        self.held = 0;
+        self.holding = false;
+    }
+
+    // まだ出力していない入力フレーム数
+    fn buffered_frames(&self) -> f64 {
+        self.frames() as f64 - self.position
+    }
+
+    fn frames(&self) -> usize {
+        self.buffer.len() / self.out_channels
+    }
+
+    /// 入力コールバックから呼ぶ
+    pub fn push(&mut self, data: &[f32]) {
+        for frame in data.chunks_exact(self.in_channels) {
+            for channel in 0..self.out_channels {
+                let sample = if self.out_channels == 1 && self.in_channels > 1 {
+                    // モノラル出力には全チャンネルを混ぜる
+                    frame.iter().sum::<f32>() / self.in_channels as f32
+                } else {
+                    // モノラル入力は全チャンネルに、それ以外は順に割り当てる
+                    frame[channel % self.in_channels]
+                };
+                self.buffer.push_back(sample);
+            }
+        }
+        if self.frames() >= self.target_frames {
+            self.primed = true;
+        }
+
+        // 出力が止まっていても際限なくたまらないようにする
+        let max_frames = self.target_frames * 2 + self.in_rate as usize;
+        if self.frames() > max_frames {
+            let excess = (self.frames() - max_frames) * self.out_channels;
+            self.buffer.drain(0..excess);
+        }
+    }
+
+    /// 出力コールバックから呼ぶ。遅延分たまるまでと、足りなくなったときは無音を出す
+    pub fn pull(&mut self, out: &mut [f32]) {
+        let generation = DUMP_GENERATION.load(Ordering::SeqCst);
+        if generation != self.dump_generation {
+            self.dump_generation = generation;
+            self.dump();
+        }
+
+        for frame in out.chunks_mut(self.out_channels) {
+            let index = self.position as usize;
+            if !self.primed || index + 1 >= self.frames() {
+                frame.fill(0.0);
+                continue;
+            }
+
+            let fraction = (self.position - index as f64) as f32;
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let current = self.buffer[index * self.out_channels + channel];
+                let next = self.buffer[(index + 1) * self.out_channels + channel];
+                *sample = current + (next - current) * fraction;
+            }
+
+            // 遅延が大きく足りないとき（ダンプ直後など）は、無音が続いている間だけ読み出し位置を止めて同じ値を出す。
+            // 引き延ばすのは、それまでに続いた無音の長さまで（無音の部分が最大2倍になる）。
+            // 少しずつ止めると音が細切れになるので、引き延ばした長さの2倍まで無音が続くごとにまとめて止める
+            let quiet = frame.iter().all(|sample| sample.abs() < QUIET_LEVEL);
+            let short = self.buffered_frames() + (self.rebuild_threshold_frames as f64) < self.target_frames as f64;
+            if !quiet {
+                self.quiet_run = 0;
+                self.held = 0;
+                self.holding = false;
+            } else if short
+                && self.held < self.quiet_run
+                && (self.holding || self.quiet_run >= self.quiet_min_frames.max(self.held * 2))
+            {
+                self.holding = true;
+                self.held += 1;
+                continue;
+            } else {
+                self.holding = false;
+                self.quiet_run += 1;
+            }
+
+            // たまっている量が目標より多ければ速く、少なければゆっくり再生する
+            let error = self.buffered_frames() - self.target_frames as f64;
+            let correction = (error / self.in_rate * MAX_CORRECTION).clamp(-MAX_CORRECTION, MAX_CORRECTION);
+            self.position += self.rate_ratio * (1.0 + correction);
+
+            let consumed = (self.position as usize).min(self.frames());
+            self.buffer.drain(0..consumed * self.out_channels);
+            self.position -= consumed as f64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1ms = 1フレームになるようにモノラル1kHzで試す
+    const RATE: u32 = 1000;
+    const BLOCK: usize = 10;
+    const DELAY_MS: u32 = 1000;
+    const LOUD: f32 = 0.5;
+
+    // 入力と出力を同じ量ずつ進める
+    fn run(line: &mut DelayLine, level: f32, ms: usize) -> Vec<f32> {
+        let mut output = Vec::new();
+        for _ in 0..ms / BLOCK {
+            line.push(&[level; BLOCK]);
+            let mut out = [0.0; BLOCK];
+            line.pull(&mut out);
+            output.extend(out);
+        }
+        output
+    }
+
+    fn primed_line() -> DelayLine {
+        let mut line = DelayLine::new(DELAY_MS, 1, RATE, 1, RATE);
+        run(&mut line, LOUD, DELAY_MS as usize + 500);
+        line
+    }
+
+    #[test]
+    fn keeps_the_delay() {
+        let mut line = primed_line();
+        run(&mut line, LOUD, 5000);
+        assert!((line.buffered_frames() - DELAY_MS as f64).abs() < 20.0);
+    }
+
+    #[test]
+    fn pauses_rebuild_the_delay_after_dump() {
+        let mut line = primed_line();
+        line.dump();
+        // 話す・間をあけるを繰り返す
+        for _ in 0..20 {
+            run(&mut line, LOUD, 300);
+            run(&mut line, 0.0, 300);
+        }
+        assert!(line.buffered_frames() + REBUILD_THRESHOLD_MS as f64 >= DELAY_MS as f64);
+    }
+
+    #[test]
+    fn loud_audio_is_not_stretched() {
+        let mut line = primed_line();
+        line.dump();
+        let output = run(&mut line, LOUD, 2000);
+        // 止めて同じ値を出すのは無音のときだけで、音のある部分は再生速度の補正分しか遅れない
+        assert!(line.buffered_frames() < 2000.0 * MAX_CORRECTION + BLOCK as f64 * 2.0);
+        assert!(output.iter().skip(BLOCK * 2).all(|&sample| sample == LOUD));
+    }
+
+    #[test]
+    fn pauses_are_at_most_doubled() {
+        let mut line = primed_line();
+        line.dump();
+        run(&mut line, LOUD, 1000);
+        let before = line.buffered_frames();
+        run(&mut line, 0.0, 200);
+        // 無音の200ms分を超えては引き延ばさない
+        assert!(line.buffered_frames() - before <= 200.0 + BLOCK as f64);
+    }
+}
@@ -0,0 +1,180 @@
+//デバイスごとの対応サンプルレート・サンプル形式を調べて、録音に使う設定を選ぶ
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{SampleFormat, SampleRate, SupportedStreamConfig, SupportedStreamConfigRange};
+use std::collections::HashMap;
+
+/// 選択肢として表示するサンプルレート
+pub const SAMPLE_RATE_CHOICES: [u32; 5] = [16000, 44100, 48000, 96000, 192000];
+
+/// 録音できるサンプル形式（先頭ほど優先）
+pub const SAMPLE_FORMAT_CHOICES: [SampleFormat; 4] = [
+    SampleFormat::F32,
+    SampleFormat::I32,
+    SampleFormat::I16,
+    SampleFormat::U16,
+];
+
+/// 入力デバイスの一覧（表示名, `input_devices()` での番号）。ホストから一覧を取得できなければErr
+pub fn list_input_devices() -> Result<Vec<(String, usize)>, String> {
+    let host = cpal::default_host();
+    let devices = host.input_devices().map_err(|e| e.to_string())?;
+    Ok(devices
+        .enumerate()
+        .filter_map(|(i, device)| device.name().ok().map(|name| (display_name(name, i), i)))
+        .collect())
+}
+
+// 名前が空のデバイスは番号で呼ぶ
+fn display_name(name: String, index: usize) -> String {
+    if name.is_empty() {
+        format!("入力デバイス {}", index + 1)
+    } else {
+        name
+    }
+}
+
+/// 表示名が `device_name` の入力デバイスを開く。`index_hint` は一覧を取ったときの番号で、
+/// デバイスの抜き差しで番号がずれていたら名前で探し直す
+pub fn find_input_device(device_name: &str, index_hint: usize) -> Option<cpal::Device> {
+    let host = cpal::default_host();
+    let matches = |(i, device): &(usize, cpal::Device)| {
+        device.name().is_ok_and(|name| display_name(name, *i) == device_name)
+    };
+    let hinted = host
+        .input_devices()
+        .ok()?
+        .enumerate()
+        .nth(index_hint)
+        .filter(&matches);
+    match hinted {
+        Some((_, device)) => Some(device),
+        None => host
+            .input_devices()
+            .ok()?
+            .enumerate()
+            .find(matches)
+            .map(|(_, device)| device),
+    }
+}
+
+/// 出力デバイスの名前の一覧（モニター出力先の選択用）
+pub fn list_output_devices() -> Vec<String> {
+    let host = cpal::default_host();
+    match host.output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(e) => {
+            eprintln!("出力デバイスの取得エラー: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// 既定の入力デバイス。一覧に無ければ先頭、一覧が空ならNone
+pub fn default_input_device(input_devices: &[(String, usize)]) -> Option<(String, usize)> {
+    let host = cpal::default_host();
+    let default_name = host.default_input_device().and_then(|device| device.name().ok());
+    input_devices
+        .iter()
+        .find(|(name, _)| Some(name) == default_name.as_ref())
+        .or(input_devices.first())
+        .cloned()
+}
+
+/// "f32" などの名前からサンプル形式を得る。"auto"や不明な名前ならNone
+pub fn parse_sample_format(name: &str) -> Option<SampleFormat> {
+    SAMPLE_FORMAT_CHOICES
+        .into_iter()
+        .find(|format| format.to_string() == name)
+}
+
+/// 入力デバイスが対応している設定の一覧。取得できなければ空
+pub fn query_supported_configs(device_name: &str, device_index: usize) -> Vec<SupportedStreamConfigRange> {
+    match find_input_device(device_name, device_index) {
+        Some(device) => supported_configs(&device),
+        None => Vec::new(),
+    }
+}
+
+/// 全ての入力デバイスの対応設定（`input_devices()` での番号ごと）。時間がかかるので別スレッドで呼ぶ
+pub fn query_all_supported_configs() -> HashMap<usize, Vec<SupportedStreamConfigRange>> {
+    let host = cpal::default_host();
+    match host.input_devices() {
+        Ok(devices) => devices
+            .enumerate()
+            .map(|(i, device)| (i, supported_configs(&device)))
+            .collect(),
+        Err(e) => {
+            eprintln!("入力デバイスの取得エラー: {}", e);
+            HashMap::new()
+        }
+    }
+}
+
+/// 録音できるサンプル形式に絞った、デバイスの対応設定
+pub fn supported_configs(device: &cpal::Device) -> Vec<SupportedStreamConfigRange> {
+    match device.supported_input_configs() {
+        Ok(configs) => configs
+            .filter(|c| SAMPLE_FORMAT_CHOICES.contains(&c.sample_format()))
+            .collect(),
+        Err(e) => {
+            eprintln!("対応設定の取得エラー: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// サンプル形式の指定（Noneなら自動）に合う設定だけを返す
+fn matching(
+    configs: &[SupportedStreamConfigRange],
+    sample_format: Option<SampleFormat>,
+) -> impl Iterator<Item = &SupportedStreamConfigRange> {
+    configs
+        .iter()
+        .filter(move |c| sample_format.is_none_or(|format| c.sample_format() == format))
+}
+
+pub fn supports_sample_rate(
+    configs: &[SupportedStreamConfigRange],
+    sample_rate: u32,
+    sample_format: Option<SampleFormat>,
+) -> bool {
+    matching(configs, sample_format)
+        .any(|c| c.min_sample_rate().0 <= sample_rate && sample_rate <= c.max_sample_rate().0)
+}
+
+pub fn supports_sample_format(configs: &[SupportedStreamConfigRange], sample_format: SampleFormat) -> bool {
+    configs.iter().any(|c| c.sample_format() == sample_format)
+}
+
+/// 指定したサンプルレートに一番近い、対応しているサンプルレート
+pub fn nearest_sample_rate(
+    configs: &[SupportedStreamConfigRange],
+    sample_rate: u32,
+    sample_format: Option<SampleFormat>,
+) -> Option<u32> {
+    matching(configs, sample_format)
+        .map(|c| sample_rate.clamp(c.min_sample_rate().0, c.max_sample_rate().0))
+        .min_by_key(|rate| rate.abs_diff(sample_rate))
+}
+
+/// 録音に使う設定を選ぶ。サンプルレートが対応していなければ一番近いものにする
+pub fn choose_config(
+    configs: &[SupportedStreamConfigRange],
+    sample_rate: u32,
+    sample_format: Option<SampleFormat>,
+) -> Option<SupportedStreamConfig> {
+    matching(configs, sample_format)
+        .min_by_key(|c| {
+            let rate = sample_rate.clamp(c.min_sample_rate().0, c.max_sample_rate().0);
+            let format_rank = SAMPLE_FORMAT_CHOICES
+                .iter()
+                .position(|format| *format == c.sample_format())
+                .unwrap_or(usize::MAX);
+            // レートの近さ > 形式の優先度 > ステレオ/モノラル
+            (rate.abs_diff(sample_rate), format_rank, c.channels() > 2)
+        })
+        .map(|c| {
+            let rate = sample_rate.clamp(c.min_sample_rate().0, c.max_sample_rate().0);
+            c.with_sample_rate(SampleRate(rate))
+        })
+}
@@ -0,0 +1,267 @@
+//入力デバイスの音を少しだけ聞いて、何がつながっていそうかを推定する
+//
+// 録音前にマイクの差し間違い・ケーブル抜けに気づけるように、
+// 音量（RMS）とスペクトル重心（音の明るさ）から大まかなラベルを付ける。
+// デバイスを選んだときに別スレッドで短く録音して分析し、録音を始めたときは録音の最初の部分で分析し直す
+use crate::device_config;
+use crate::recorder;
+
+use cpal::traits::{DeviceTrait, StreamTrait};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// 分析のために録音する長さ
+const PROBE_DURATION: Duration = Duration::from_millis(1000);
+/// 止めるように言われていないか確かめる間隔
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+/// スペクトルを求める窓の長さと、平均する窓の数
+const WINDOW_SIZE: usize = 1024;
+const MAX_WINDOWS: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DeviceLabel {
+    /// ほぼ無音（ケーブル抜け・ミュート・何もつながっていない）
+    Silent,
+    /// 低い音ばかり（電源ハム・グラウンドループ）
+    Hum,
+    /// 小さめの音で声の帯域（部屋の音を拾っているマイク）
+    VoiceMic,
+    /// 大きめの音で広い帯域（ライン入力の音楽など）
+    LineMusic,
+    /// 高い音ばかり（ヒスノイズ）
+    Noise,
+    /// デバイスを開けなかった
+    Unavailable,
+}
+
+impl DeviceLabel {
+    /// 表示用の翻訳キー
+    pub fn key(self) -> &'static str {
+        match self {
+            DeviceLabel::Silent => "label_silent",
+            DeviceLabel::Hum => "label_hum",
+            DeviceLabel::VoiceMic => "label_voice_mic",
+            DeviceLabel::LineMusic => "label_line_music",
+            DeviceLabel::Noise => "label_noise",
+            DeviceLabel::Unavailable => "label_unavailable",
+        }
+    }
+
+    /// 録音前に確認したほうがよさそうな結果か
+    pub fn is_warning(self) -> bool {
+        !matches!(self, DeviceLabel::VoiceMic | DeviceLabel::LineMusic)
+    }
+}
+
+/// 別スレッドで動いている分析
+pub struct ProbeHandle {
+    cancel: Arc<AtomicBool>,
+    handle: thread::JoinHandle<()>,
+}
+
+impl ProbeHandle {
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// 分析を止めて、デバイスを閉じ終わるまで待つ。止めた分析の結果は書き込まれない
+    pub fn stop(self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        if self.handle.join().is_err() {
+            eprintln!("デバイス分析スレッドが異常終了しました");
+        }
+    }
+}
+
+/// デバイスを別スレッドで短時間録音して分析し、結果を `label` に書き込む
+/// （分析中に異常終了しても、分析中のままにならないように「使えない」にする）
+pub fn spawn(device_name: String, device_index: usize, label: Arc<Mutex<Option<DeviceLabel>>>) -> ProbeHandle {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let cancel_clone = cancel.clone();
+    let handle = thread::spawn(move || {
+        let result = panic::catch_unwind(|| capture(&device_name, device_index, &cancel_clone));
+        if cancel_clone.load(Ordering::Relaxed) {
+            return;
+        }
+        let device_label = match result {
+            Ok(Some((samples, sample_rate))) => classify(&samples, sample_rate),
+            Ok(None) | Err(_) => DeviceLabel::Unavailable,
+        };
+        *label.lock().unwrap() = Some(device_label);
+    });
+    ProbeHandle { cancel, handle }
+}
+
+/// 分析に使うサンプル数（1チャンネル分）
+pub fn probe_length(sample_rate: u32) -> usize {
+    (PROBE_DURATION.as_millis() as u64 * sample_rate as u64 / 1000) as usize
+}
+
+// 先頭チャンネルのサンプルを集める。止めるように言われたらNone
+fn capture(device_name: &str, device_index: usize, cancel: &AtomicBool) -> Option<(Vec<f32>, u32)> {
+    let device = device_config::find_input_device(device_name, device_index)?;
+    let default_rate = device.default_input_config().ok()?.sample_rate().0;
+    let config = device_config::choose_config(&device_config::supported_configs(&device), default_rate, None)?;
+    let sample_rate = config.sample_rate().0;
+    let channels = config.channels().max(1) as usize;
+
+    let samples = Arc::new(Mutex::new(Vec::new()));
+    let samples_clone = samples.clone();
+    let on_data = move |data: &[f32]| {
+        samples_clone.lock().unwrap().extend(data.iter().step_by(channels));
+    };
+    let err_fn = |err: cpal::StreamError| eprintln!("デバイス分析エラー: {:?}", err);
+
+    let sample_format = config.sample_format();
+    let stream_config: cpal::StreamConfig = config.into();
+    let stream = match sample_format {
+        cpal::SampleFormat::F32 => recorder::build_input_stream::<f32>(&device, &stream_config, on_data, err_fn),
+        cpal::SampleFormat::I32 => recorder::build_input_stream::<i32>(&device, &stream_config, on_data, err_fn),
+        cpal::SampleFormat::I16 => recorder::build_input_stream::<i16>(&device, &stream_config, on_data, err_fn),
+        cpal::SampleFormat::U16 => recorder::build_input_stream::<u16>(&device, &stream_config, on_data, err_fn),
+        _ => return None,
+    };
+    let stream = match stream {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!("デバイス分析用のストリーム作成エラー: {}", e);
+            return None;
+        }
+    };
+    stream.play().ok()?;
+    let mut elapsed = Duration::ZERO;
+    while elapsed < PROBE_DURATION {
+        if cancel.load(Ordering::Relaxed) {
+            return None;
+        }
+        thread::sleep(CANCEL_CHECK_INTERVAL);
+        elapsed += CANCEL_CHECK_INTERVAL;
+    }
+    drop(stream);
+
+    let samples = samples.lock().unwrap().clone();
+    Some((samples, sample_rate))
+}
+
+/// 先頭チャンネルのサンプルから、つながっていそうなものを推定する
+pub fn classify(samples: &[f32], sample_rate: u32) -> DeviceLabel {
+    // 1窓分も届かないデバイスは、音が来ていないものとして扱う
+    if samples.len() < WINDOW_SIZE {
+        return DeviceLabel::Silent;
+    }
+
+    let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+    let rms_db = 20.0 * rms.max(1e-10).log10();
+    if rms_db < -70.0 {
+        return DeviceLabel::Silent;
+    }
+
+    let centroid = spectral_centroid(samples, sample_rate);
+    if centroid < 200.0 {
+        DeviceLabel::Hum
+    } else if centroid > 5000.0 {
+        DeviceLabel::Noise
+    } else if rms_db > -30.0 {
+        DeviceLabel::LineMusic
+    } else {
+        DeviceLabel::VoiceMic
+    }
+}
+
+// スペクトル重心（Hz）。短いので素朴なDFTで求める
+fn spectral_centroid(samples: &[f32], sample_rate: u32) -> f32 {
+    let tau = std::f32::consts::TAU;
+    let hann: Vec<f32> = (0..WINDOW_SIZE)
+        .map(|n| 0.5 - 0.5 * (tau * n as f32 / (WINDOW_SIZE - 1) as f32).cos())
+        .collect();
+
+    let mut spectrum = vec![0.0f32; WINDOW_SIZE / 2];
+    for window in samples.chunks_exact(WINDOW_SIZE).take(MAX_WINDOWS) {
+        let windowed: Vec<f32> = window.iter().zip(&hann).map(|(s, w)| s * w).collect();
+        // 直流成分（k = 0）は飛ばす
+        for (k, magnitude) in spectrum.iter_mut().enumerate().skip(1) {
+            let (mut re, mut im) = (0.0f32, 0.0f32);
+            for (n, &sample) in windowed.iter().enumerate() {
+                let phase = tau * ((k * n) % WINDOW_SIZE) as f32 / WINDOW_SIZE as f32;
+                re += sample * phase.cos();
+                im -= sample * phase.sin();
+            }
+            *magnitude += (re * re + im * im).sqrt();
+        }
+    }
+
+    let total: f32 = spectrum.iter().sum();
+    if total <= 0.0 {
+        return 0.0;
+    }
+    let bin_hz = sample_rate as f32 / WINDOW_SIZE as f32;
+    spectrum
+        .iter()
+        .enumerate()
+        .map(|(k, magnitude)| k as f32 * bin_hz * magnitude)
+        .sum::<f32>()
+        / total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn sine(frequency: f32, amplitude: f32) -> Vec<f32> {
+        (0..probe_length(SAMPLE_RATE))
+            .map(|n| amplitude * (std::f32::consts::TAU * frequency * n as f32 / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+
+    // 再現できるように、固定の種から作る白色雑音
+    fn white_noise(amplitude: f32) -> Vec<f32> {
+        let mut state = 0x1234_5678u32;
+        (0..probe_length(SAMPLE_RATE))
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                amplitude * (state as f32 / u32::MAX as f32 * 2.0 - 1.0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn too_short_is_silent() {
+        assert_eq!(classify(&[0.5; WINDOW_SIZE - 1], SAMPLE_RATE), DeviceLabel::Silent);
+    }
+
+    #[test]
+    fn quiet_input_is_silent() {
+        // -80 dB程度
+        assert_eq!(classify(&sine(1000.0, 0.0001), SAMPLE_RATE), DeviceLabel::Silent);
+    }
+
+    #[test]
+    fn low_tone_is_hum() {
+        assert_eq!(classify(&sine(60.0, 0.1), SAMPLE_RATE), DeviceLabel::Hum);
+    }
+
+    #[test]
+    fn broadband_noise_is_noise() {
+        // 白色雑音の重心はナイキスト周波数の半分（12kHz）あたり
+        assert_eq!(classify(&white_noise(0.01), SAMPLE_RATE), DeviceLabel::Noise);
+    }
+
+    #[test]
+    fn loud_midrange_is_line_music() {
+        // -9 dB程度
+        assert_eq!(classify(&sine(1000.0, 0.5), SAMPLE_RATE), DeviceLabel::LineMusic);
+    }
+
+    #[test]
+    fn quiet_midrange_is_voice_mic() {
+        // -43 dB程度
+        assert_eq!(classify(&sine(1000.0, 0.01), SAMPLE_RATE), DeviceLabel::VoiceMic);
+    }
+}
@@ -0,0 +1,318 @@
+//大きな音を検出してクリップを書き出す
+use chrono::Local;
+use hound::{WavSpec, WavWriter};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+/// イベントクリップの保存先フォルダ（録音ファイルの保存先からの相対）
+pub const EVENTS_DIR: &str = "events";
+
+/// 1クリップの最大長（秒）。これを超えたら次のクリップに分ける
+const MAX_CLIP_SECONDS: usize = 60;
+
+/// 書き出しスレッドに送るもの
+enum ClipMessage {
+    Start,
+    Samples(Vec<f32>),
+    End,
+}
+
+enum DetectorState {
+    Idle,
+    Capturing,
+}
+
+/// 録音中の音量を監視して、大きな音のイベントを検出する
+///
+/// スレッショルドを超えた状態が `min_duration_ms` 続いたらイベント開始とし、
+/// 前後に `padding_ms` 分の余白をつけたクリップを `events` フォルダに書き出す。
+/// オーディオコールバックでは判定だけを行い、サンプルはチャンネルで書き出しスレッドに送る
+pub struct EventDetector {
+    threshold_amp: f32,
+    min_duration_samples: usize,
+    padding_samples: usize,
+    max_clip_samples: usize,
+    state: DetectorState,
+    pre_buffer: VecDeque<f32>,
+    clip_samples: usize,
+    loud_samples: usize,
+    quiet_samples: usize,
+    sender: Sender<ClipMessage>,
+}
+
+impl EventDetector {
+    /// # 引数
+    /// - `threshold_db`: 検出スレッショルド（例: -20.0）
+    /// - `min_duration_ms`: イベントとみなす最短の継続時間
+    /// - `padding_ms`: クリップの前後につける余白
+    /// - `channels` / `sample_rate`: 入力ストリームの形式
+    /// - `output_dir`: 録音ファイルの保存先。クリップはその下の `events` に書き出す
+    pub fn new(
+        threshold_db: f32,
+        min_duration_ms: u32,
+        padding_ms: u32,
+        channels: u16,
+        sample_rate: u32,
+        device_name: String,
+        output_dir: &Path,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let spec = WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let events_dir = output_dir.join(EVENTS_DIR);
+        thread::spawn(move || write_clips(receiver, events_dir, device_name, spec));
+
+        Self::with_sender(threshold_db, min_duration_ms, padding_ms, channels, sample_rate, sender)
+    }
+
+    fn with_sender(
+        threshold_db: f32,
+        min_duration_ms: u32,
+        padding_ms: u32,
+        channels: u16,
+        sample_rate: u32,
+        sender: Sender<ClipMessage>,
+    ) -> Self {
+        let samples_per_ms = sample_rate as usize * channels as usize / 1000;
+        let min_duration_samples = min_duration_ms as usize * samples_per_ms;
+        let padding_samples = padding_ms as usize * samples_per_ms;
+        // コールバック1回分（最大100ms程度）はみ出しても確保し直さないようにしておく
+        let pre_buffer_capacity = padding_samples + min_duration_samples + 100 * samples_per_ms;
+        Self {
+            threshold_amp: 10f32.powf(threshold_db / 20.0),
+            min_duration_samples,
+            padding_samples,
+            max_clip_samples: MAX_CLIP_SECONDS * sample_rate as usize * channels as usize,
+            state: DetectorState::Idle,
+            pre_buffer: VecDeque::with_capacity(pre_buffer_capacity),
+            clip_samples: 0,
+            loud_samples: 0,
+            quiet_samples: 0,
+            sender,
+        }
+    }
+
+    /// 入力コールバックごとのサンプル列を渡す
+    pub fn process(&mut self, data: &[f32]) {
+        if data.is_empty() {
+            return;
+        }
+
+        // ブロック単位のRMSで判定する（サンプル単位だとゼロクロスで途切れるため）
+        let rms = (data.iter().map(|s| s * s).sum::<f32>() / data.len() as f32).sqrt();
+        let loud = rms >= self.threshold_amp;
+
+        match self.state {
+            DetectorState::Idle => {
+                self.pre_buffer.extend(data.iter().copied());
+                let capacity = self.padding_samples + self.min_duration_samples;
+                if self.pre_buffer.len() > capacity {
+                    let excess = self.pre_buffer.len() - capacity;
+                    self.pre_buffer.drain(0..excess);
+                }
+
+                if loud {
+                    self.loud_samples += data.len();
+                } else {
+                    self.loud_samples = 0;
+                }
+
+                if loud && self.loud_samples >= self.min_duration_samples {
+                    let pre_samples: Vec<f32> = self.pre_buffer.drain(..).collect();
+                    self.clip_samples = pre_samples.len();
+                    self.quiet_samples = 0;
+                    self.state = DetectorState::Capturing;
+                    self.send(ClipMessage::Start);
+                    self.send(ClipMessage::Samples(pre_samples));
+                }
+            }
+            DetectorState::Capturing => {
+                // 最大長を超える分は次のクリップに入れる（途切れないように続けて記録する）
+                let room = self.max_clip_samples.saturating_sub(self.clip_samples).min(data.len());
+                let (current, rest) = data.split_at(room);
+                if !current.is_empty() {
+                    self.clip_samples += current.len();
+                    self.send(ClipMessage::Samples(current.to_vec()));
+                }
+                if !rest.is_empty() {
+                    self.send(ClipMessage::End);
+                    self.send(ClipMessage::Start);
+                    self.clip_samples = rest.len();
+                    self.send(ClipMessage::Samples(rest.to_vec()));
+                }
+
+                if loud {
+                    self.quiet_samples = 0;
+                } else {
+                    self.quiet_samples += data.len();
+                }
+
+                if self.quiet_samples >= self.padding_samples {
+                    self.end_clip();
+                }
+            }
+        }
+    }
+
+    /// 録音停止時に呼ぶ。検出中のイベントがあれば書き出す
+    pub fn finish(&mut self) {
+        if matches!(self.state, DetectorState::Capturing) {
+            self.end_clip();
+        }
+    }
+
+    fn end_clip(&mut self) {
+        self.state = DetectorState::Idle;
+        self.clip_samples = 0;
+        self.loud_samples = 0;
+        self.quiet_samples = 0;
+        self.send(ClipMessage::End);
+    }
+
+    fn send(&self, message: ClipMessage) {
+        // 書き出しスレッドが終わっていたら捨てる（エラーはスレッド側で表示済み）
+        let _ = self.sender.send(message);
+    }
+}
+
+// 書き出しスレッドの本体。検出器が無くなる（送信側が閉じる）まで動く
+fn write_clips(receiver: Receiver<ClipMessage>, events_dir: PathBuf, device_name: String, spec: WavSpec) {
+    let mut writer: Option<WavWriter<BufWriter<File>>> = None;
+    for message in receiver {
+        match message {
+            ClipMessage::Start => {
+                let filename = events_dir.join(format!(
+                    "{}-{}-event.wav",
+                    Local::now().format("%Y-%m-%d-%H-%M-%S"),
+                    device_name.replace(" ", "_")
+                ));
+                writer = match create_clip(&events_dir, &filename, spec) {
+                    Ok(writer) => Some(writer),
+                    Err(e) => {
+                        eprintln!("イベントクリップ作成エラー: {}", e);
+                        None
+                    }
+                };
+            }
+            ClipMessage::Samples(samples) => {
+                if let Some(clip_writer) = writer.as_mut() {
+                    let result = samples
+                        .iter()
+                        .try_for_each(|&sample| clip_writer.write_sample((sample * i16::MAX as f32) as i16));
+                    if let Err(e) = result {
+                        eprintln!("イベントクリップ書き込みエラー: {}", e);
+                        writer = None;
+                    }
+                }
+            }
+            ClipMessage::End => finalize_clip(writer.take()),
+        }
+    }
+    finalize_clip(writer);
+}
+
+fn create_clip(events_dir: &Path, filename: &Path, spec: WavSpec) -> Result<WavWriter<BufWriter<File>>, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(events_dir)?;
+    Ok(WavWriter::create(filename, spec)?)
+}
+
+fn finalize_clip(writer: Option<WavWriter<BufWriter<File>>>) {
+    if let Some(Err(e)) = writer.map(WavWriter::finalize) {
+        eprintln!("イベントクリップ書き込みエラー: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1ms = 1サンプルになるようにモノラル1kHzで試す
+    const SAMPLE_RATE: u32 = 1000;
+    const BLOCK: usize = 10;
+    const LOUD: f32 = 0.5;
+    const QUIET: f32 = 0.001;
+
+    fn detector(min_duration_ms: u32, padding_ms: u32) -> (EventDetector, Receiver<ClipMessage>) {
+        let (sender, receiver) = mpsc::channel();
+        (EventDetector::with_sender(-20.0, min_duration_ms, padding_ms, 1, SAMPLE_RATE, sender), receiver)
+    }
+
+    fn feed(detector: &mut EventDetector, level: f32, ms: usize) {
+        for _ in 0..ms / BLOCK {
+            detector.process(&[level; BLOCK]);
+        }
+    }
+
+    // 受け取ったメッセージをクリップごとにまとめる
+    fn clips(receiver: &Receiver<ClipMessage>) -> Vec<Vec<f32>> {
+        let mut clips = Vec::new();
+        let mut current: Option<Vec<f32>> = None;
+        for message in receiver.try_iter() {
+            match message {
+                ClipMessage::Start => current = Some(Vec::new()),
+                ClipMessage::Samples(samples) => current.as_mut().expect("Start の前にサンプル").extend(samples),
+                ClipMessage::End => clips.push(current.take().expect("Start の前に End")),
+            }
+        }
+        assert!(current.is_none(), "閉じていないクリップがある");
+        clips
+    }
+
+    #[test]
+    fn short_burst_does_not_trigger() {
+        let (mut detector, receiver) = detector(100, 50);
+        feed(&mut detector, QUIET, 500);
+        feed(&mut detector, LOUD, 90);
+        feed(&mut detector, QUIET, 500);
+        detector.finish();
+        assert!(clips(&receiver).is_empty());
+    }
+
+    #[test]
+    fn triggers_after_min_duration() {
+        let (mut detector, receiver) = detector(100, 50);
+        feed(&mut detector, QUIET, 500);
+        feed(&mut detector, LOUD, 100);
+        assert_eq!(receiver.try_iter().filter(|m| matches!(m, ClipMessage::Start)).count(), 1);
+    }
+
+    #[test]
+    fn clip_has_padding_on_both_sides() {
+        let (mut detector, receiver) = detector(100, 50);
+        feed(&mut detector, QUIET, 500);
+        feed(&mut detector, LOUD, 200);
+        feed(&mut detector, QUIET, 500);
+
+        let clips = clips(&receiver);
+        assert_eq!(clips.len(), 1);
+        let clip = &clips[0];
+        let leading = clip.iter().take_while(|&&s| s == QUIET).count();
+        let trailing = clip.iter().rev().take_while(|&&s| s == QUIET).count();
+        assert_eq!(leading, 50);
+        assert_eq!(trailing, 50);
+        assert_eq!(clip.len(), 50 + 200 + 50);
+    }
+
+    #[test]
+    fn long_event_is_split_at_max_clip_length() {
+        let (mut detector, receiver) = detector(100, 50);
+        let max_clip_samples = MAX_CLIP_SECONDS * SAMPLE_RATE as usize;
+        feed(&mut detector, LOUD, max_clip_samples * 2 + 5000);
+        detector.finish();
+
+        let clips = clips(&receiver);
+        assert_eq!(clips.len(), 3);
+        assert_eq!(clips[0].len(), max_clip_samples);
+        assert_eq!(clips[1].len(), max_clip_samples);
+        // 分けたところで音が抜けていない
+        assert_eq!(clips.iter().map(Vec::len).sum::<usize>(), max_clip_samples * 2 + 5000);
+    }
+}
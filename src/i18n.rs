@@ -48,8 +48,13 @@ impl From<Language> for &'static str {
     }
 }
 
-pub fn init_i18n() -> I18nConfig {
-    I18nConfig::new(langid!("ja"))
+/// `language` は起動時の言語（設定・コマンドラインの上書きを反映したもの）
+pub fn init_i18n(language: Language) -> I18nConfig {
+    let initial = match language {
+        Language::Japanese => langid!("ja"),
+        Language::English => langid!("en"),
+    };
+    I18nConfig::new(initial)
         .with_locale(Locale::new_static(
             langid!("ja"),
             include_str!("../locales/ja.ftl"),
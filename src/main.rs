@@ -4,7 +4,19 @@ use dioxus_i18n::prelude::*;
 mod record_page;
 mod setting_page;
 mod effect;
+mod device_config;
+mod event_capture;
 mod i18n;
+mod config_path;
+mod metrics;
+mod overrides;
+mod shutdown;
+mod recorder;
+mod daemon;
+mod delay_line;
+mod device_probe;
+mod service;
+mod tls;
 
 #[derive(Clone, Copy, PartialEq)]
 enum Page {
@@ -13,7 +25,7 @@ enum Page {
 }
 
 fn app() -> Element {
-    use_init_i18n(|| i18n::init_i18n());
+    use_init_i18n(|| i18n::init_i18n(setting_page::AppSettings::effective().language));
     let mut current_page = use_signal(|| Page::Recording);
 
     rsx! {
@@ -33,5 +45,65 @@ fn app() -> Element {
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| matches!(arg.as_str(), "--ctl" | "--daemon" | "--install-service" | "--uninstall-service")) {
+        daemon::attach_console();
+    }
+
+    // バックグラウンド録音をOSの起動時に動くように登録・解除する
+    for (flag, action) in [
+        ("--install-service", service::install as fn() -> Result<String, String>),
+        ("--uninstall-service", service::uninstall),
+    ] {
+        if args.iter().any(|arg| arg == flag) {
+            match action() {
+                Ok(message) => println!("{}", message),
+                Err(e) => {
+                    eprintln!("{} に失敗しました: {}", flag, e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+    }
+    let mut settings = setting_page::AppSettings::load_and_migrate();
+    overrides::apply(&mut settings);
+    overrides::report_invalid();
+
+    // 常駐中のプロセスにコマンドを送る（既定は status）
+    if let Some(pos) = args.iter().position(|arg| arg == "--ctl") {
+        let command = args.get(pos + 1).map(String::as_str).unwrap_or("status");
+        match daemon::send_command(&settings, command) {
+            Ok(response) => {
+                println!("{}", response);
+                if response.starts_with("error") {
+                    std::process::exit(1);
+                }
+            }
+            Err(e) => {
+                eprintln!("バックグラウンド録音に接続できません: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if settings.metrics_enabled {
+        metrics::start_server(settings.metrics_port);
+    }
+    shutdown::install_handlers();
+
+    // GUIなしで常駐して、--ctl からのコマンドで録音する
+    if args.iter().any(|arg| arg == "--daemon") {
+        if let Err(e) = daemon::run(&settings) {
+            eprintln!("バックグラウンド録音の起動エラー: {}", e);
+            std::process::exit(1);
+        }
+        shutdown::shutdown_and_wait();
+        return;
+    }
+
     launch_with_title(app,"録音くん");
+    // ウィンドウを閉じたときも録音中のファイルを書き出してから終わる
+    shutdown::shutdown_and_wait();
 }
@@ -0,0 +1,287 @@
+//監視用のメトリクスをPrometheus形式で公開する
+use crate::setting_page::AppSettings;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// リクエスト行の最大長と、リクエストを送ってこない接続を待つ時間
+const MAX_REQUEST_LINE_LENGTH: u64 = 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// デバイスカードごとのメトリクス（録音していないカードも recording=0 で出す）
+pub struct DeviceMetrics {
+    /// 録音ページのカードの番号（同じデバイスを2枚のカードで使っても区別できるように）
+    pub card_index: usize,
+    pub device_name: String,
+    pub recording: AtomicBool,
+    /// 直近のブロックのピークレベル（f32のビット列）
+    peak_level: AtomicU32,
+    pub dropouts: AtomicU64,
+    pub samples_written: AtomicU64,
+    /// エンコード待ちのサンプル数（FLACバッファなど）
+    pub queue_depth: AtomicU64,
+}
+
+impl DeviceMetrics {
+    pub fn set_peak_level(&self, level: f32) {
+        self.peak_level.store(level.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn peak_level(&self) -> f32 {
+        f32::from_bits(self.peak_level.load(Ordering::Relaxed))
+    }
+}
+
+static DEVICES: Mutex<Vec<Arc<DeviceMetrics>>> = Mutex::new(Vec::new());
+
+impl DeviceMetrics {
+    fn new(card_index: usize, device_name: &str) -> Self {
+        Self {
+            card_index,
+            device_name: device_name.to_string(),
+            recording: AtomicBool::new(false),
+            peak_level: AtomicU32::new(0),
+            dropouts: AtomicU64::new(0),
+            samples_written: AtomicU64::new(0),
+            queue_depth: AtomicU64::new(0),
+        }
+    }
+}
+
+/// 設定されているカードの一覧（カードの番号順のデバイス名）を反映する。
+/// 止まっているカードも recording=0 で残るので、「録音が止まった」を監視できる
+pub fn configure_devices(device_names: &[String]) {
+    let mut devices = DEVICES.lock().unwrap();
+    let configured = device_names
+        .iter()
+        .enumerate()
+        .map(|(card_index, device_name)| {
+            devices
+                .iter()
+                .find(|m| m.card_index == card_index && m.device_name == *device_name)
+                .cloned()
+                .unwrap_or_else(|| Arc::new(DeviceMetrics::new(card_index, device_name)))
+        })
+        .collect();
+    *devices = configured;
+}
+
+/// 録音スレッドの開始時に呼ぶ。戻り値はスレッド内で更新する
+pub fn register_device(card_index: usize, device_name: &str) -> Arc<DeviceMetrics> {
+    let mut devices = DEVICES.lock().unwrap();
+    let metrics = match devices
+        .iter()
+        .find(|m| m.card_index == card_index && m.device_name == device_name)
+    {
+        Some(metrics) => metrics.clone(),
+        None => {
+            let metrics = Arc::new(DeviceMetrics::new(card_index, device_name));
+            devices.retain(|m| m.card_index != card_index);
+            devices.push(metrics.clone());
+            devices.sort_by_key(|m| m.card_index);
+            metrics
+        }
+    };
+    metrics.recording.store(true, Ordering::Relaxed);
+    metrics
+}
+
+/// 録音スレッドが終わったら（異常終了も含む）カードの系列を recording=0 にして残す
+pub struct FinishGuard(Arc<DeviceMetrics>);
+
+impl Drop for FinishGuard {
+    fn drop(&mut self) {
+        self.0.recording.store(false, Ordering::Relaxed);
+        self.0.set_peak_level(0.0);
+        self.0.queue_depth.store(0, Ordering::Relaxed);
+    }
+}
+
+/// `register_device` の直後に呼んで、録音スレッドが終わるまで持っておく
+pub fn finish_on_drop(metrics: &Arc<DeviceMetrics>) -> FinishGuard {
+    FinishGuard(metrics.clone())
+}
+
+/// Prometheusのテキスト形式でメトリクスを書き出す
+pub fn render() -> String {
+    let devices = DEVICES.lock().unwrap();
+    let mut out = String::new();
+
+    let _ = writeln!(out, "# HELP rokuon_recording_devices Number of devices currently recording.");
+    let _ = writeln!(out, "# TYPE rokuon_recording_devices gauge");
+    let _ = writeln!(
+        out,
+        "rokuon_recording_devices {}",
+        devices.iter().filter(|d| d.recording.load(Ordering::Relaxed)).count()
+    );
+
+    let _ = writeln!(out, "# HELP rokuon_recording Whether the device is recording (1) or not (0).");
+    let _ = writeln!(out, "# TYPE rokuon_recording gauge");
+    for d in devices.iter() {
+        let _ = writeln!(
+            out,
+            "rokuon_recording{{card=\"{}\",device=\"{}\"}} {}",
+            d.card_index,
+            escape_label(&d.device_name),
+            d.recording.load(Ordering::Relaxed) as u8
+        );
+    }
+
+    let _ = writeln!(out, "# HELP rokuon_peak_level Peak input level of the latest block (0.0-1.0).");
+    let _ = writeln!(out, "# TYPE rokuon_peak_level gauge");
+    for d in devices.iter() {
+        let _ = writeln!(
+            out,
+            "rokuon_peak_level{{card=\"{}\",device=\"{}\"}} {}",
+            d.card_index,
+            escape_label(&d.device_name),
+            d.peak_level()
+        );
+    }
+
+    let _ = writeln!(out, "# HELP rokuon_dropouts_total Stream errors reported by the audio backend.");
+    let _ = writeln!(out, "# TYPE rokuon_dropouts_total counter");
+    for d in devices.iter() {
+        let _ = writeln!(
+            out,
+            "rokuon_dropouts_total{{card=\"{}\",device=\"{}\"}} {}",
+            d.card_index,
+            escape_label(&d.device_name),
+            d.dropouts.load(Ordering::Relaxed)
+        );
+    }
+
+    let _ = writeln!(out, "# HELP rokuon_samples_written_total Samples written since the recording started.");
+    let _ = writeln!(out, "# TYPE rokuon_samples_written_total counter");
+    for d in devices.iter() {
+        let _ = writeln!(
+            out,
+            "rokuon_samples_written_total{{card=\"{}\",device=\"{}\"}} {}",
+            d.card_index,
+            escape_label(&d.device_name),
+            d.samples_written.load(Ordering::Relaxed)
+        );
+    }
+
+    let _ = writeln!(out, "# HELP rokuon_queue_depth_samples Samples buffered in memory waiting to be encoded.");
+    let _ = writeln!(out, "# TYPE rokuon_queue_depth_samples gauge");
+    for d in devices.iter() {
+        let _ = writeln!(
+            out,
+            "rokuon_queue_depth_samples{{card=\"{}\",device=\"{}\"}} {}",
+            d.card_index,
+            escape_label(&d.device_name),
+            d.queue_depth.load(Ordering::Relaxed)
+        );
+    }
+
+    // 保存先のドライブの空き容量
+    let output_dir = AppSettings::effective().output_dir;
+    let output_dir = if output_dir.is_empty() { ".".to_string() } else { output_dir };
+    if let Some(free_bytes) = disk_free_bytes(Path::new(&output_dir)) {
+        let _ = writeln!(out, "# HELP rokuon_disk_free_bytes Free space available on the output directory's volume.");
+        let _ = writeln!(out, "# TYPE rokuon_disk_free_bytes gauge");
+        let _ = writeln!(
+            out,
+            "rokuon_disk_free_bytes{{path=\"{}\"}} {}",
+            escape_label(&output_dir),
+            free_bytes
+        );
+    }
+
+    out
+}
+
+// 指定したパスがあるボリュームの空き容量（一般ユーザーが使える分）
+// statvfsのフィールドの型はOSによって違うので、そろえるためにキャストする
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)]
+fn disk_free_bytes(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(windows)]
+fn disk_free_bytes(path: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let mut free_bytes: u64 = 0;
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(wide.as_ptr(), &mut free_bytes, std::ptr::null_mut(), std::ptr::null_mut())
+    };
+    if ok == 0 { None } else { Some(free_bytes) }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn disk_free_bytes(_path: &Path) -> Option<u64> {
+    None
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// `127.0.0.1:<port>/metrics` でメトリクスを公開するサーバーを起動する
+pub fn start_server(port: u16) {
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("メトリクスサーバー起動エラー: {}", e);
+            return;
+        }
+    };
+
+    // 1つずつ応答する。何も送ってこない接続は READ_TIMEOUT で切るので、他の取得は止まらない
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = handle_connection(stream) {
+                        eprintln!("メトリクス応答エラー: {}", e);
+                    }
+                }
+                Err(e) => eprintln!("メトリクス接続エラー: {}", e),
+            }
+        }
+    });
+}
+
+fn handle_connection(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    stream.set_write_timeout(Some(READ_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new((&stream).take(MAX_REQUEST_LINE_LENGTH)).read_line(&mut request_line)?;
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    let (status, content_type, body) = if request_line.starts_with("GET ") && path == "/metrics" {
+        ("200 OK", "text/plain; version=0.0.4", render())
+    } else {
+        ("404 Not Found", "text/plain", "not found\n".to_string())
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,282 @@
+//コマンドライン引数・環境変数による設定の上書き
+//
+// settings.jsonのキー名に対応していて、優先度は コマンドライン > 環境変数 > settings.json
+// 例: `--audio-format flac` / `--format=flac` / `ROKUON_OUTPUT_DIR=D:\rec`
+use crate::i18n::Language;
+use crate::setting_page::{AppSettings, AudioFormat};
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// 上書きできる設定項目（settings.jsonのキー名）
+const KEYS: &[&str] = &[
+    "audio_format",
+    "sample_rate",
+    "bit_depth",
+    "output_dir",
+    "compressor_enabled",
+    "compressor_threshold_db",
+    "compressor_ratio",
+    "event_capture_enabled",
+    "event_threshold_db",
+    "event_min_duration_ms",
+    "event_padding_ms",
+    "metrics_enabled",
+    "metrics_port",
+    "daemon_port",
+    "daemon_address",
+    "daemon_tls_cert",
+    "daemon_tls_key",
+    "monitor_enabled",
+    "monitor_delay_ms",
+    "monitor_device",
+    "language",
+];
+
+/// 短い別名
+const ALIASES: &[(&str, &str)] = &[("format", "audio_format")];
+
+/// 設定項目以外のフラグ（main.rs / config_path.rsで読む）
+const OTHER_FLAGS: &[&str] = &["portable", "daemon", "ctl", "install-service", "uninstall-service"];
+
+/// WAVに書き込めるビット深度
+const BIT_DEPTHS: &[u16] = &[16, 24, 32];
+
+const ENV_PREFIX: &str = "ROKUON_";
+
+static OVERRIDES: OnceLock<Vec<(&'static str, String)>> = OnceLock::new();
+
+/// 今回の実行で有効な上書き（キー, 値）。後のものほど優先される
+pub fn overrides() -> &'static [(&'static str, String)] {
+    OVERRIDES.get_or_init(|| collect(std::env::args().skip(1), std::env::vars()))
+}
+
+/// 上書きされている設定項目の名前
+pub fn active_keys() -> Vec<&'static str> {
+    let mut keys: Vec<&'static str> = overrides().iter().map(|(key, _)| *key).collect();
+    keys.sort_unstable();
+    keys.dedup();
+    keys
+}
+
+/// 設定に上書きを適用する。不正な値は無視する（何度も呼ばれるので、ログは `report_invalid` で1回だけ出す）
+pub fn apply(settings: &mut AppSettings) {
+    for (key, value) in overrides() {
+        let _ = apply_one(settings, key, value);
+    }
+}
+
+/// 不正な上書きをログに出す。起動時に1回だけ呼ぶ
+pub fn report_invalid() {
+    for (key, value, error) in invalid(overrides()) {
+        eprintln!("設定の上書きエラー ({}={}): {}", key, value, error);
+    }
+}
+
+// 適用できない上書き（キー, 値, 理由）
+fn invalid(overrides: &[(&'static str, String)]) -> Vec<(&'static str, String, String)> {
+    let mut settings = AppSettings::default();
+    overrides
+        .iter()
+        .filter_map(|(key, value)| {
+            apply_one(&mut settings, key, value)
+                .err()
+                .map(|error| (*key, value.clone(), error))
+        })
+        .collect()
+}
+
+fn collect(
+    args: impl Iterator<Item = String>,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Vec<(&'static str, String)> {
+    let mut result = Vec::new();
+
+    // 環境変数（コマンドラインより優先度が低いので先に入れる）
+    for (name, value) in vars {
+        if let Some(key) = name
+            .strip_prefix(ENV_PREFIX)
+            .and_then(|rest| lookup(&rest.to_lowercase()))
+        {
+            result.push((key, value));
+        }
+    }
+
+    // コマンドライン引数。設定項目以外のフラグ（--portableなど）は読み飛ばし、
+    // 知らないフラグは打ち間違いに気づけるように警告する
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            continue;
+        };
+        let (name, inline_value) = match flag.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (flag, None),
+        };
+        let Some(key) = lookup(&name.replace('-', "_")) else {
+            if !OTHER_FLAGS.contains(&name) {
+                eprintln!("不明なフラグ --{} を無視します", name);
+            }
+            continue;
+        };
+        match inline_value.or_else(|| args.next()) {
+            Some(value) => result.push((key, value)),
+            None => eprintln!("--{} に値が指定されていません", name),
+        }
+    }
+
+    result
+}
+
+fn lookup(name: &str) -> Option<&'static str> {
+    KEYS.iter()
+        .copied()
+        .find(|key| *key == name)
+        .or_else(|| {
+            ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == name)
+                .map(|(_, key)| *key)
+        })
+}
+
+fn apply_one(settings: &mut AppSettings, key: &str, value: &str) -> Result<(), String> {
+    match key {
+        "audio_format" => {
+            settings.audio_format = match value.to_lowercase().as_str() {
+                "wave" | "wav" => AudioFormat::Wave,
+                "pcm" => AudioFormat::Pcm,
+                "flac" => AudioFormat::Flac,
+                _ => return Err("WAVE / PCM / FLAC のいずれかを指定してください".to_string()),
+            }
+        }
+        "sample_rate" => settings.sample_rate = parse(value)?,
+        "bit_depth" => {
+            let bit_depth = parse(value)?;
+            if !BIT_DEPTHS.contains(&bit_depth) {
+                return Err("16 / 24 / 32 のいずれかを指定してください".to_string());
+            }
+            settings.bit_depth = bit_depth;
+        }
+        "output_dir" => settings.output_dir = value.to_string(),
+        "compressor_enabled" => settings.compressor_enabled = parse_bool(value)?,
+        "compressor_threshold_db" => settings.compressor_threshold_db = parse(value)?,
+        "compressor_ratio" => settings.compressor_ratio = parse(value)?,
+        "event_capture_enabled" => settings.event_capture_enabled = parse_bool(value)?,
+        "event_threshold_db" => settings.event_threshold_db = parse(value)?,
+        "event_min_duration_ms" => settings.event_min_duration_ms = parse(value)?,
+        "event_padding_ms" => settings.event_padding_ms = parse(value)?,
+        "metrics_enabled" => settings.metrics_enabled = parse_bool(value)?,
+        "metrics_port" => settings.metrics_port = parse(value)?,
+        "daemon_port" => settings.daemon_port = parse(value)?,
+        "daemon_address" => settings.daemon_address = value.trim().to_string(),
+        "daemon_tls_cert" => settings.daemon_tls_cert = value.to_string(),
+        "daemon_tls_key" => settings.daemon_tls_key = value.to_string(),
+        "monitor_enabled" => settings.monitor_enabled = parse_bool(value)?,
+        "monitor_delay_ms" => settings.monitor_delay_ms = parse(value)?,
+        "monitor_device" => settings.monitor_device = value.to_string(),
+        "language" => {
+            settings.language = match value {
+                "ja" => Language::Japanese,
+                "en" => Language::English,
+                _ => return Err("ja / en のいずれかを指定してください".to_string()),
+            }
+        }
+        _ => return Err("不明な設定項目です".to_string()),
+    }
+    Ok(())
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    value.trim().parse().map_err(|e: T::Err| e.to_string())
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "1" | "on" | "yes" => Ok(true),
+        "false" | "0" | "off" | "no" => Ok(false),
+        _ => Err("true / false を指定してください".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> impl Iterator<Item = String> {
+        list.iter().map(|s| s.to_string()).collect::<Vec<_>>().into_iter()
+    }
+
+    fn vars(list: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        list.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn applied(overrides: &[(&'static str, String)]) -> AppSettings {
+        let mut settings = AppSettings::default();
+        for (key, value) in overrides {
+            apply_one(&mut settings, key, value).unwrap();
+        }
+        settings
+    }
+
+    #[test]
+    fn command_line_wins_over_environment() {
+        let overrides = collect(
+            args(&["--sample-rate", "48000"]),
+            vars(&[("ROKUON_SAMPLE_RATE", "96000"), ("ROKUON_BIT_DEPTH", "24")]),
+        );
+        let settings = applied(&overrides);
+        assert_eq!(settings.sample_rate, 48000);
+        assert_eq!(settings.bit_depth, 24);
+    }
+
+    #[test]
+    fn inline_and_separate_values() {
+        let overrides = collect(args(&["--sample-rate=16000", "--output-dir", "rec"]), vars(&[]));
+        assert_eq!(
+            overrides,
+            vec![("sample_rate", "16000".to_string()), ("output_dir", "rec".to_string())]
+        );
+    }
+
+    #[test]
+    fn format_alias() {
+        let overrides = collect(args(&["--format", "flac"]), vars(&[]));
+        assert_eq!(overrides, vec![("audio_format", "flac".to_string())]);
+        assert!(matches!(applied(&overrides).audio_format, AudioFormat::Flac));
+    }
+
+    #[test]
+    fn other_flags_are_skipped() {
+        let overrides = collect(args(&["--portable", "--ctl", "status", "--sample-rat", "1"]), vars(&[]));
+        assert!(overrides.is_empty());
+    }
+
+    #[test]
+    fn unrelated_environment_is_ignored() {
+        let overrides = collect(args(&[]), vars(&[("PATH", "/bin"), ("ROKUON_UNKNOWN", "1")]));
+        assert!(overrides.is_empty());
+    }
+
+    #[test]
+    fn bit_depth_is_limited() {
+        let mut settings = AppSettings::default();
+        assert!(apply_one(&mut settings, "bit_depth", "8").is_err());
+        assert!(apply_one(&mut settings, "bit_depth", "24").is_ok());
+        assert_eq!(settings.bit_depth, 24);
+    }
+
+    #[test]
+    fn invalid_overrides_are_listed() {
+        let overrides = collect(args(&["--language", "fr", "--sample-rate", "48000"]), vars(&[]));
+        let invalid = invalid(&overrides);
+        assert_eq!(invalid.len(), 1);
+        assert_eq!((invalid[0].0, invalid[0].1.as_str()), ("language", "fr"));
+    }
+}
@@ -1,20 +1,21 @@
 use freya::prelude::*;
-use crate::setting_page::{AppSettings, AudioFormat};
-use crate::effect;
-
-use chrono::Local;
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use hound::{WavSpec, WavWriter};
-use flacenc::{
-    config::Encoder as FlacEncoder, 
-    source::MemSource, 
-    bitsink::ByteSink, 
-    component::BitRepr,
-    error::Verify
-};
+use crate::setting_page::{self, AppSettings, DeviceSetup, SettingsNotice};
+use crate::delay_line;
+use crate::daemon;
+use crate::device_config;
+use crate::metrics;
+use crate::device_probe::{self, DeviceLabel};
+use crate::recorder::{self, RecordOptions};
+
+use dioxus_i18n::t;
 use std::{
-    sync::{Arc, Mutex},
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
     thread,
+    time::Duration,
 };
 
 #[derive(Clone)]
@@ -24,8 +25,138 @@ struct RecordingDevice {
     is_recording: bool,
     waveform_data: Arc<Mutex<Vec<f32>>>,
     recording_start_time: Option<std::time::Instant>,
+    estimated_bytes: Arc<AtomicU64>,
+    sample_rate: u32,
+    // Noneなら自動で選ぶ
+    sample_format: Option<cpal::SampleFormat>,
+    supported_configs: Vec<cpal::SupportedStreamConfigRange>,
+    gain_db: f32,
+    compressor_enabled: bool,
+    // 保存されていたデバイスが見つからず、代わりのデバイスを使っているときの元の名前
+    missing_device_name: Option<String>,
+    // 入力の音から推定した、つながっていそうなもの（分析中・まだ分析していなければNone）
+    device_label: Arc<Mutex<Option<DeviceLabel>>>,
+    // 動いている分析。録音を始める前に止める
+    probe: Arc<Mutex<Option<device_probe::ProbeHandle>>>,
 }
 
+impl RecordingDevice {
+    fn new(device_index: usize, device_name: String, supported_configs: Vec<cpal::SupportedStreamConfigRange>) -> Self {
+        let settings = AppSettings::effective();
+        let mut device = Self {
+            device_index,
+            device_name,
+            is_recording: false,
+            waveform_data: Arc::new(Mutex::new(vec![0.0; 200])),
+            recording_start_time: None,
+            estimated_bytes: Arc::new(AtomicU64::new(0)),
+            sample_rate: settings.sample_rate,
+            sample_format: None,
+            supported_configs: Vec::new(),
+            gain_db: 0.0,
+            compressor_enabled: settings.compressor_enabled,
+            missing_device_name: None,
+            device_label: Arc::new(Mutex::new(None)),
+            probe: Arc::new(Mutex::new(None)),
+        };
+        device.set_supported_configs(supported_configs);
+        device.start_probe();
+        device
+    }
+
+    // 保存されていた構成から復元する。同じ名前のデバイスが無ければ `fallback` を使う
+    fn from_setup(
+        setup: &DeviceSetup,
+        input_devices: &[(String, usize)],
+        fallback: &(String, usize),
+        supported_configs: &SupportedConfigs,
+    ) -> Self {
+        let found = input_devices.iter().find(|(name, _)| *name == setup.device_name);
+        let (device_name, device_index) = found.unwrap_or(fallback).clone();
+        let configs = supported_configs.get(&device_index).cloned().unwrap_or_default();
+
+        let mut device = Self::new(device_index, device_name, configs.clone());
+        device.sample_rate = setup.sample_rate;
+        device.sample_format = device_config::parse_sample_format(&setup.sample_format);
+        device.gain_db = setup.gain_db;
+        device.compressor_enabled = setup.compressor_enabled;
+        if found.is_none() {
+            device.missing_device_name = Some(setup.device_name.clone());
+        }
+        device.set_supported_configs(configs);
+        device
+    }
+
+    fn record_options(&self, card_index: usize) -> RecordOptions {
+        RecordOptions {
+            card_index,
+            device_index: self.device_index,
+            device_name: self.device_name.clone(),
+            sample_rate: self.sample_rate,
+            sample_format: self.sample_format,
+            gain_db: self.gain_db,
+            compressor_enabled: self.compressor_enabled,
+        }
+    }
+
+    fn to_setup(&self) -> DeviceSetup {
+        DeviceSetup {
+            // 見つからなかったデバイスは、元の名前のまま保存しておく
+            device_name: self.missing_device_name.clone().unwrap_or_else(|| self.device_name.clone()),
+            gain_db: self.gain_db,
+            compressor_enabled: self.compressor_enabled,
+            sample_rate: self.sample_rate,
+            sample_format: match self.sample_format {
+                Some(format) => format.to_string(),
+                None => "auto".to_string(),
+            },
+        }
+    }
+
+    // 選んだデバイスの音を別スレッドで分析する。前の分析は止めて、結果も使わない
+    // 録音中は録音の最初の部分で分析するので、ここでは何もしない
+    fn start_probe(&mut self) {
+        if self.is_recording {
+            return;
+        }
+        self.stop_probe();
+        self.device_label = Arc::new(Mutex::new(None));
+        let probe = device_probe::spawn(self.device_name.clone(), self.device_index, self.device_label.clone());
+        *self.probe.lock().unwrap() = Some(probe);
+    }
+
+    // 動いている分析を止めて、デバイスを閉じ終わるまで待つ
+    fn stop_probe(&self) {
+        let probe = self.probe.lock().unwrap().take();
+        if let Some(probe) = probe {
+            probe.stop();
+        }
+    }
+
+    fn is_probing(&self) -> bool {
+        self.probe.lock().unwrap().as_ref().is_some_and(|probe| !probe.is_finished())
+    }
+
+    // 調べ直した対応設定に入れ替える
+    fn set_supported_configs(&mut self, supported_configs: Vec<cpal::SupportedStreamConfigRange>) {
+        self.supported_configs = supported_configs;
+        self.fit_to_supported_configs();
+    }
+
+    // サンプルレート・形式を対応しているものに合わせる
+    fn fit_to_supported_configs(&mut self) {
+        if self.sample_format.is_some_and(|format| !device_config::supports_sample_format(&self.supported_configs, format)) {
+            self.sample_format = None;
+        }
+        if let Some(rate) = device_config::nearest_sample_rate(&self.supported_configs, self.sample_rate, self.sample_format) {
+            self.sample_rate = rate;
+        }
+    }
+}
+
+// 入力デバイスの番号ごとの対応設定
+type SupportedConfigs = HashMap<usize, Vec<cpal::SupportedStreamConfigRange>>;
+
 #[derive(Clone)]
 struct AppState {
     recording_devices: Vec<RecordingDevice>,
@@ -34,28 +165,140 @@ struct AppState {
 
 impl AppState {
     fn new() -> Self {
-        let host = cpal::default_host();
-        let input_devices: Vec<(String, usize)> = host
-            .input_devices()
-            .unwrap()
-            .enumerate()
-            .filter_map(|(i, device)| {
-                device.name().ok().map(|name| {
-                    let display_name = if name.is_empty() {
-                        format!("入力デバイス {}", i + 1)
-                    } else {
-                        name
-                    };
-                    (display_name, i)
-                })
-            })
-            .collect();
+        let input_devices = device_config::list_input_devices().unwrap_or_else(|e| {
+            eprintln!("入力デバイスの取得エラー: {}", e);
+            Vec::new()
+        });
+        let supported_configs = device_config::query_all_supported_configs();
 
         Self {
-            recording_devices: vec![],
+            recording_devices: restore_devices(&input_devices, &supported_configs),
             input_devices,
         }
     }
+
+    // 取り直した入力デバイスの一覧に入れ替える。録音中のデバイスはそのまま録音を続ける
+    fn set_input_devices(&mut self, input_devices: Vec<(String, usize)>, supported_configs: &SupportedConfigs) {
+        // 起動時にデバイスが1つも無かった場合は、見つかったときに前回の構成を復元する
+        if self.input_devices.is_empty() && self.recording_devices.is_empty() {
+            self.recording_devices = restore_devices(&input_devices, supported_configs);
+        }
+        let fallback = device_config::default_input_device(&input_devices);
+
+        for device in &mut self.recording_devices {
+            // 番号は一覧の並びで変わるので、名前で探し直す
+            if device.is_recording {
+                // 録音中は番号だけ合わせておく（録音は開いたデバイスで続く）
+                if let Some((_, index)) = input_devices.iter().find(|(name, _)| *name == device.device_name) {
+                    device.device_index = *index;
+                }
+                continue;
+            }
+
+            let original_name = device.missing_device_name.clone().unwrap_or_else(|| device.device_name.clone());
+            match input_devices.iter().find(|(name, _)| *name == original_name) {
+                Some((name, index)) => {
+                    if device.device_name != *name || device.device_index != *index {
+                        // 見つからなかったデバイスが接続されたら元のデバイスに戻す
+                        device.device_name = name.clone();
+                        device.device_index = *index;
+                        device.missing_device_name = None;
+                        device.set_supported_configs(supported_configs.get(index).cloned().unwrap_or_default());
+                        device.start_probe();
+                    }
+                }
+                None => {
+                    // 抜かれたデバイスは代わりのデバイスにしておく（代わりのデバイスの番号も取り直す）
+                    let substitute = input_devices
+                        .iter()
+                        .find(|(name, _)| *name == device.device_name)
+                        .cloned()
+                        .or_else(|| fallback.clone())
+                        .filter(|(name, index)| device.device_name != *name || device.device_index != *index);
+                    if let Some((name, index)) = substitute {
+                        device.missing_device_name = Some(original_name);
+                        device.device_name = name;
+                        device.set_supported_configs(supported_configs.get(&index).cloned().unwrap_or_default());
+                        device.device_index = index;
+                        device.start_probe();
+                    }
+                }
+            }
+        }
+
+        self.input_devices = input_devices;
+    }
+}
+
+// 時間のかかる処理を別スレッドで動かして、UIを止めずに終わるのを待つ
+async fn run_blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Option<T> {
+    let handle = thread::spawn(f);
+    while !handle.is_finished() {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    handle.join().ok()
+}
+
+// 常駐しているバックグラウンド録音にコマンドを送る。動いていなければNone
+async fn send_daemon_command(command: &'static str) -> Option<String> {
+    let settings = AppSettings::effective();
+    run_blocking(move || daemon::send_command(&settings, command).ok()).await.flatten()
+}
+
+// 前回のデバイス構成を復元する。入力デバイスが無ければ空
+fn restore_devices(input_devices: &[(String, usize)], supported_configs: &SupportedConfigs) -> Vec<RecordingDevice> {
+    match device_config::default_input_device(input_devices) {
+        Some(fallback) => AppSettings::load()
+            .devices
+            .iter()
+            .map(|setup| RecordingDevice::from_setup(setup, input_devices, &fallback, supported_configs))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+// 入力デバイスの一覧を入れ替えて、増えたカードの分の録音ハンドル・停止フラグを用意する
+fn apply_input_devices(
+    mut app_state: Signal<AppState>,
+    mut recorder_handles: Signal<Vec<Option<thread::JoinHandle<()>>>>,
+    mut stop_flags: Signal<Vec<Arc<Mutex<bool>>>>,
+    input_devices: Vec<(String, usize)>,
+    supported_configs: &SupportedConfigs,
+) {
+    app_state.write().set_input_devices(input_devices, supported_configs);
+    let count = app_state.peek().recording_devices.len();
+    if recorder_handles.peek().len() < count {
+        recorder_handles.write().resize_with(count, || None);
+        stop_flags.write().resize_with(count, || Arc::new(Mutex::new(false)));
+    }
+}
+
+// 入力デバイスの一覧と対応設定を別スレッドで取り直して、変わっていれば反映する
+// （デバイスの列挙はバックエンドによって時間がかかるので、UIを止めないようにする）
+// 一覧を取得できなかったときは、前の一覧のままにする
+async fn refresh_input_devices(
+    app_state: Signal<AppState>,
+    recorder_handles: Signal<Vec<Option<thread::JoinHandle<()>>>>,
+    stop_flags: Signal<Vec<Arc<Mutex<bool>>>>,
+) {
+    let previous = app_state.peek().input_devices.clone();
+    let scan = run_blocking(move || -> Result<Option<(Vec<(String, usize)>, SupportedConfigs)>, String> {
+        let input_devices = device_config::list_input_devices()?;
+        if input_devices == previous {
+            return Ok(None);
+        }
+        Ok(Some((input_devices, device_config::query_all_supported_configs())))
+    })
+    .await;
+
+    match scan {
+        Some(Ok(Some((input_devices, supported_configs)))) => {
+            apply_input_devices(app_state, recorder_handles, stop_flags, input_devices, &supported_configs);
+        }
+        Some(Ok(None)) => {}
+        Some(Err(e)) => eprintln!("入力デバイスの取得エラー: {}", e),
+        None => eprintln!("入力デバイスの検出が異常終了しました"),
+    }
 }
 
 #[component]
@@ -84,208 +327,30 @@ fn RecordingButton(
                         // 全デバイスの録音開始
                         for &device_idx in &device_idxs {
                             if device_idx < app_state.read().recording_devices.len() {
+                                // 分析中のデバイスは閉じてから録音に使う。ラベルは録音の最初の部分で付け直す
+                                app_state.read().recording_devices[device_idx].stop_probe();
+                                app_state.write().recording_devices[device_idx].device_label = Arc::new(Mutex::new(None));
                                 app_state.write().recording_devices[device_idx].is_recording = true;
                                 app_state.write().recording_devices[device_idx].recording_start_time = Some(std::time::Instant::now());
+                                app_state.read().recording_devices[device_idx].estimated_bytes.store(0, Ordering::Relaxed);
 
                                 if device_idx < stop_flags.read().len() {
                                     *stop_flags.read()[device_idx].lock().unwrap() = false;
                                 }
 
-                                let selected_device_index = app_state.read().recording_devices[device_idx].device_index;
-                                let device_name = app_state.read().recording_devices[device_idx].device_name.clone();
+                                let options = app_state.read().recording_devices[device_idx].record_options(device_idx);
                                 let stop_flag_clone = if device_idx < stop_flags.read().len() {
                                     stop_flags.read()[device_idx].clone()
                                 } else {
                                     Arc::new(Mutex::new(false))
                                 };
                                 let waveform_data_clone = app_state.read().recording_devices[device_idx].waveform_data.clone();
+                                let estimated_bytes_clone = app_state.read().recording_devices[device_idx].estimated_bytes.clone();
+                                let device_label_clone = app_state.read().recording_devices[device_idx].device_label.clone();
 
                                 let handle = thread::spawn(move || {
-                                    let settings = AppSettings::load();
-                                    let host = cpal::default_host();
-                                    let device = host
-                                        .input_devices()
-                                        .unwrap()
-                                        .nth(selected_device_index)
-                                        .expect("選択されたデバイスが見つかりません");
-                                    let config = device.default_input_config().unwrap();
-
-                                    let now = Local::now();
-                                    let (filename, writer_opt) = match settings.audio_format {
-                                        AudioFormat::Wave => {
-                                            let spec = WavSpec {
-                                                channels: config.channels(),
-                                                sample_rate: settings.sample_rate,
-                                                bits_per_sample: settings.bit_depth,
-                                                sample_format: hound::SampleFormat::Int,
-                                            };
-                                            let filename = format!("{}-{}.wav",
-                                                now.format("%Y-%m-%d-%H-%M-%S"),
-                                                device_name.replace(" ", "_")
-                                            );
-                                            let writer = WavWriter::create(&filename, spec).unwrap();
-                                            (filename, Some(Arc::new(Mutex::new(Some(writer)))))
-                                        },
-                                        AudioFormat::Pcm => {
-                                            let filename = format!("{}-{}.pcm",
-                                                now.format("%Y-%m-%d-%H-%M-%S"),
-                                                device_name.replace(" ", "_")
-                                            );
-                                            (filename, None)
-                                        },
-                                        AudioFormat::Flac => {
-                                            let filename = format!("{}-{}.flac",
-                                                now.format("%Y-%m-%d-%H-%M-%S"),
-                                                device_name.replace(" ", "_")
-                                            );
-                                            (filename, None)
-                                        },
-                                    };
-
-                                    let pcm_file = if matches!(settings.audio_format, AudioFormat::Pcm) {
-                                        Some(Arc::new(Mutex::new(std::fs::File::create(&filename).unwrap())))
-                                    } else {
-                                        None
-                                    };
-
-                                    // FLAC用のサンプルバッファ
-                                    let flac_samples = if matches!(settings.audio_format, AudioFormat::Flac) {
-                                        Some(Arc::new(Mutex::new(Vec::<i32>::new())))
-                                    } else {
-                                        None
-                                    };
-
-                                    let err_fn = |err| eprintln!("録音エラー: {:?}", err);
-                                    let writer_clone = writer_opt.clone();
-                                    let pcm_file_clone = pcm_file.clone();
-                                    let flac_samples_clone = flac_samples.clone();
-                                    let stop_flag_stream = Arc::clone(&stop_flag_clone);
-                                    let waveform_clone = waveform_data_clone.clone();
-                                    let format = settings.audio_format.clone();
-                                    
-                                    // コンプレッサー設定をローカル変数にコピー
-                                    let compressor_enabled = settings.compressor_enabled;
-                                    // let compressor_threshold_db = settings.compressor_threshold_db;
-                                    // let compressor_ratio = settings.compressor_ratio;
-                                    let compressor_threshold_db:f32 = -20.0;
-                                    let compressor_ratio:f32 = 4.0;
-
-                                    let stream = match config.sample_format() {
-                                        cpal::SampleFormat::F32 => device.build_input_stream(
-                                            &config.into(),
-                                            move |data: &[f32], _| {
-                                                if *stop_flag_stream.lock().unwrap() {
-                                                    return;
-                                                }
-
-                                                // コンプレッサーを適用（設定で有効な場合）
-                                                let processed_data = if compressor_enabled {
-                                                    effect::compress_audio(
-                                                        data,
-                                                        compressor_threshold_db,
-                                                        compressor_ratio
-                                                    )
-                                                } else {
-                                                    data.to_vec()
-                                                };
-
-                                                // 波形データを更新
-                                                {
-                                                    let mut waveform = waveform_clone.lock().unwrap();
-                                                    waveform.clear();
-                                                    waveform.extend_from_slice(&processed_data);
-                                                    if waveform.len() > 300 {
-                                                        let len = waveform.len();
-                                                        waveform.drain(0..len-300);
-                                                    }
-                                                }
-
-                                                // フォーマットに応じてデータを書き込み
-                                                match format {
-                                                    AudioFormat::Wave => {
-                                                        if let Some(ref writer_arc) = writer_clone {
-                                                            let mut writer_lock = writer_arc.lock().unwrap();
-                                                            if let Some(writer) = writer_lock.as_mut() {
-                                                                for &sample in &processed_data {
-                                                                    let sample_i16 = (sample * i16::MAX as f32) as i16;
-                                                                    writer.write_sample(sample_i16).unwrap();
-                                                                }
-                                                            }
-                                                        }
-                                                    },
-                                                    AudioFormat::Pcm => {
-                                                        if let Some(ref pcm_file_arc) = pcm_file_clone {
-                                                            use std::io::Write;
-                                                            let mut file = pcm_file_arc.lock().unwrap();
-                                                            for &sample in &processed_data {
-                                                                let sample_i16 = (sample * i16::MAX as f32) as i16;
-                                                                file.write_all(&sample_i16.to_le_bytes()).unwrap();
-                                                            }
-                                                        }
-                                                    },
-                                                    AudioFormat::Flac => {
-                                                        if let Some(ref flac_samples_arc) = flac_samples_clone {
-                                                            let mut samples = flac_samples_arc.lock().unwrap();
-                                                            for &sample in &processed_data {
-                                                                let sample_i32 = (sample * i32::MAX as f32) as i32;
-                                                                samples.push(sample_i32);
-                                                            }
-                                                        }
-                                                    },
-                                                }
-                                            },
-                                            err_fn,
-                                            None,
-                                        ),
-                                        _ => panic!("対応していないサンプル形式"),
-                                    }.unwrap();
-
-                                    stream.play().unwrap();
-                                    while !*stop_flag_clone.lock().unwrap() {
-                                        std::thread::sleep(std::time::Duration::from_millis(100));
-                                    }
-
-                                    // フォーマットに応じてファイルを閉じる
-                                    match settings.audio_format {
-                                        AudioFormat::Wave => {
-                                            if let Some(writer_arc) = writer_opt {
-                                                writer_arc.lock().unwrap().take().unwrap().finalize().unwrap();
-                                            }
-                                        },
-                                        AudioFormat::Pcm => {
-                                            // PCMファイルは自動的に閉じられる
-                                        },
-                                        AudioFormat::Flac => {
-                                            if let Some(flac_samples_arc) = flac_samples {
-                                                let samples = flac_samples_arc.lock().unwrap();
-                                                if !samples.is_empty() {
-                                                    // FLACエンコーディング
-                                                    let config = FlacEncoder::default().into_verified().unwrap();
-                                                    let source = MemSource::from_samples(
-                                                        &samples,
-                                                        1,  // モノラルとして扱う
-                                                        settings.bit_depth as usize,
-                                                        settings.sample_rate as usize,
-                                                    );
-                                                    
-                                                    match flacenc::encode_with_fixed_block_size(
-                                                        &config, source, config.block_size
-                                                    ) {
-                                                        Ok(flac_stream) => {
-                                                            let mut sink = ByteSink::new();
-                                                            if flac_stream.write(&mut sink).is_ok() {
-                                                                if let Err(e) = std::fs::write(&filename, sink.as_slice()) {
-                                                                    eprintln!("FLACファイル書き込みエラー: {}", e);
-                                                                }
-                                                            } else {
-                                                                eprintln!("FLACストリーム書き込みエラー");
-                                                            }
-                                                        },
-                                                        Err(e) => eprintln!("FLACエンコードエラー: {}", e),
-                                                    }
-                                                }
-                                            }
-                                        },
+                                    if let Err(e) = recorder::record(options, stop_flag_clone, waveform_data_clone, estimated_bytes_clone, device_label_clone, || {}) {
+                                        eprintln!("録音エラー: {}", e);
                                     }
                                 });
 
@@ -307,7 +372,9 @@ fn RecordingButton(
 
                                 if device_idx < recorder_handles.read().len() {
                                     if let Some(handle) = recorder_handles.write()[device_idx].take() {
-                                        handle.join().unwrap();
+                                        if handle.join().is_err() {
+                                            eprintln!("録音スレッドが異常終了しました: {}", app_state.read().recording_devices[device_idx].device_name);
+                                        }
                                     }
                                 }
                             }
@@ -328,9 +395,78 @@ fn RecordingButton(
 #[component]
 pub fn record_page(on_navigate_to_settings: EventHandler<()>) -> Element {
     let mut app_state = use_signal(|| AppState::new());
+    // 復元したデバイスの数に合わせておく
     let mut recorder_handles: Signal<Vec<Option<thread::JoinHandle<()>>>> =
-        use_signal(|| Vec::new());
-    let mut stop_flags: Signal<Vec<Arc<Mutex<bool>>>> = use_signal(|| Vec::new());
+        use_signal(|| (0..app_state.peek().recording_devices.len()).map(|_| None).collect());
+    let mut stop_flags: Signal<Vec<Arc<Mutex<bool>>>> =
+        use_signal(|| (0..app_state.peek().recording_devices.len()).map(|_| Arc::new(Mutex::new(false))).collect());
+    let mut tick = use_signal(|| 0u64);
+    let mut settings_notice = use_signal(|| setting_page::take_settings_notice());
+    let monitor_enabled = use_hook(|| AppSettings::effective().monitor_enabled);
+
+    // 録音中・デバイス分析中は定期的に再描画して、録音時間と波形・分析結果を更新する
+    use_future(move || async move {
+        loop {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            if app_state
+                .peek()
+                .recording_devices
+                .iter()
+                .any(|d| d.is_recording || d.is_probing())
+            {
+                *tick.write() += 1;
+            }
+        }
+    });
+    // 後から接続・取り外しされたデバイスを反映するため、定期的に一覧を取り直す
+    // （バックエンドによっては一覧の取得でデバイスを開くので、録音中は再検出ボタンだけにする）
+    use_future(move || async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            if app_state.peek().recording_devices.iter().any(|d| d.is_recording) {
+                continue;
+            }
+            refresh_input_devices(app_state, recorder_handles, stop_flags).await;
+        }
+    });
+    // バックグラウンド録音（--daemon）の状態。動いていなければ表示しない
+    let mut daemon_status = use_signal(|| None::<String>);
+    use_future(move || async move {
+        loop {
+            daemon_status.set(send_daemon_command("status").await);
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+    });
+    // tickを購読するために読む
+    let _ = tick.read();
+
+    // デバイス構成が変わったら設定に保存する
+    use_effect(move || {
+        let state = app_state.read();
+        // 録音していないカードもメトリクスに出す
+        let names: Vec<String> = state.recording_devices.iter().map(|d| d.device_name.clone()).collect();
+        metrics::configure_devices(&names);
+
+        if state.input_devices.is_empty() {
+            return;
+        }
+        let setups: Vec<DeviceSetup> = state.recording_devices.iter().map(RecordingDevice::to_setup).collect();
+        // 読み込めない設定ファイル（壊れている・新しいバージョン）を既定値で上書きしないように、
+        // 読み込めたときだけデバイス構成を書き換えて保存する
+        let mut settings = match AppSettings::try_load() {
+            Ok(settings) => settings,
+            Err(e) => {
+                eprintln!("設定を読み込めないため、デバイス構成を保存しません: {}", e);
+                return;
+            }
+        };
+        if settings.devices != setups {
+            settings.devices = setups;
+            if let Err(e) = settings.save() {
+                eprintln!("デバイス構成の保存エラー: {}", e);
+            }
+        }
+    });
 
     rsx! {
             rect {
@@ -354,6 +490,78 @@ pub fn record_page(on_navigate_to_settings: EventHandler<()>) -> Element {
                         "録音くん"
                     }
 
+                    // 設定ファイルの移行・読み込み失敗の通知
+                    if let Some(notice) = settings_notice() {
+                        rect { height: "10" }
+                        rect {
+                            width: "100%",
+                            height: "auto",
+                            direction: "horizontal",
+                            cross_align: "center",
+                            background: match notice {
+                                SettingsNotice::Migrated { .. } => "rgb(40, 80, 120)",
+                                SettingsNotice::LoadFailed { .. } => "rgb(120, 60, 40)",
+                            },
+                            corner_radius: "4",
+                            padding: "10",
+
+                            label {
+                                color: "white",
+                                font_size: "14",
+                                width: "calc(100% - 50)",
+                                {
+                                    match notice {
+                                        SettingsNotice::Migrated { from_version, backup_path } => t!(
+                                            "settings_migrated",
+                                            from: from_version,
+                                            backup: backup_path.unwrap_or_else(|| t!("no_backup"))
+                                        ),
+                                        SettingsNotice::LoadFailed { error, backup_path } => t!(
+                                            "settings_load_failed",
+                                            error: error,
+                                            backup: backup_path.unwrap_or_else(|| t!("no_backup"))
+                                        ),
+                                    }
+                                }
+                            }
+
+                            Button {
+                                onpress: move |_| settings_notice.set(None),
+                                label { "✕" }
+                            }
+                        }
+                    }
+
+                    // バックグラウンド録音の操作
+                    if let Some(status) = daemon_status() {
+                        rect { height: "10" }
+                        rect {
+                            width: "100%",
+                            direction: "horizontal",
+                            main_align: "center",
+                            cross_align: "center",
+
+                            label {
+                                color: "rgb(180, 180, 180)",
+                                font_size: "14",
+                                {t!("daemon_status", status: status)}
+                            }
+                            rect { width: "10" }
+                            for (command, key) in [("start", "daemon_start"), ("stop", "daemon_stop")] {
+                                Button {
+                                    onpress: move |_| {
+                                        spawn(async move {
+                                            if let Some(response) = send_daemon_command(command).await {
+                                                daemon_status.set(Some(response));
+                                            }
+                                        });
+                                    },
+                                    label { {t!(key)} }
+                                }
+                            }
+                        }
+                    }
+
                     rect { height: "20" }
 
                     // 録音時間表示
@@ -363,16 +571,12 @@ pub fn record_page(on_navigate_to_settings: EventHandler<()>) -> Element {
                         text_align: "center",
                         {
                             let recording_devices = app_state.read();
-                            if let Some(device) = recording_devices.recording_devices.iter().find(|d| d.is_recording) {
-                                if let Some(start_time) = device.recording_start_time {
-                                    let elapsed = start_time.elapsed().as_secs();
-                                    format!("録音時間: {:02}:{:02}", elapsed / 60, elapsed % 60)
-                                } else {
-                                    "録音時間: 00:00".to_string()
-                                }
-                            } else {
-                                "録音時間: 00:00".to_string()
-                            }
+                            let elapsed = recording_devices.recording_devices.iter()
+                                .filter_map(|d| d.recording_start_time)
+                                .map(|start_time| start_time.elapsed().as_secs())
+                                .max()
+                                .unwrap_or(0);
+                            format!("録音時間: {}", format_elapsed(elapsed))
                         }
                     }
 
@@ -389,13 +593,8 @@ pub fn record_page(on_navigate_to_settings: EventHandler<()>) -> Element {
                                 let device_index = 0;
                                 let device_name = app_state.read().input_devices[0].0.clone();
 
-                                app_state.write().recording_devices.push(RecordingDevice {
-                                    device_index,
-                                    device_name,
-                                    is_recording: false,
-                                    waveform_data: Arc::new(Mutex::new(vec![0.0; 200])),
-                                    recording_start_time: None,
-                                });
+                                let supported_configs = device_config::query_supported_configs(&device_name, device_index);
+                                app_state.write().recording_devices.push(RecordingDevice::new(device_index, device_name, supported_configs));
 
                                 recorder_handles.write().push(None);
                                 stop_flags.write().push(Arc::new(Mutex::new(false)));
@@ -404,6 +603,14 @@ pub fn record_page(on_navigate_to_settings: EventHandler<()>) -> Element {
                         label { "➕ マイクを追加" }
                     }
 
+                    rect { width: "20" }
+                    Button {
+                        onpress: move |_| {
+                            spawn(refresh_input_devices(app_state, recorder_handles, stop_flags));
+                        },
+                        label { "{t!(\"refresh_devices\")}" }
+                    }
+
                     rect { width: "20" }
                     Button {
                         onpress: move |_| on_navigate_to_settings.call(()),
@@ -421,6 +628,15 @@ pub fn record_page(on_navigate_to_settings: EventHandler<()>) -> Element {
                             stop_flags: stop_flags,
                         }
                     }
+
+                    // モニター出力のディレイにたまっている音声を捨てる
+                    if monitor_enabled && app_state.read().recording_devices.iter().any(|d| d.is_recording) {
+                        rect { width: "20" }
+                        Button {
+                            onpress: move |_| delay_line::dump_all(),
+                            label { "{t!(\"dump\")}" }
+                        }
+                    }
                     }
 
                     rect { height: "30" }
@@ -470,8 +686,13 @@ pub fn record_page(on_navigate_to_settings: EventHandler<()>) -> Element {
                                                     to_owned![device_idx, i, name];
                                                     move |_| {
                                                         if device_idx < app_state.read().recording_devices.len() {
-                                                            app_state.write().recording_devices[device_idx].device_index = i;
-                                                            app_state.write().recording_devices[device_idx].device_name = name.clone();
+                                                            let mut state = app_state.write();
+                                                            let device = &mut state.recording_devices[device_idx];
+                                                            device.device_index = i;
+                                                            device.device_name = name.clone();
+                                                            device.missing_device_name = None;
+                                                            device.set_supported_configs(device_config::query_supported_configs(&name, i));
+                                                            device.start_probe();
                                                         }
                                                     }
                                                 },
@@ -498,10 +719,13 @@ pub fn record_page(on_navigate_to_settings: EventHandler<()>) -> Element {
                                                         }
                                                         if device_idx < recorder_handles.read().len() {
                                                             if let Some(handle) = recorder_handles.write()[device_idx].take() {
-                                                                handle.join().unwrap();
+                                                                if handle.join().is_err() {
+                                                                    eprintln!("録音スレッドが異常終了しました: {}", app_state.read().recording_devices[device_idx].device_name);
+                                                                }
                                                             }
                                                         }
                                                     }
+                                                    app_state.read().recording_devices[device_idx].stop_probe();
 
                                                     app_state.write().recording_devices.remove(device_idx);
                                                     recorder_handles.write().remove(device_idx);
@@ -516,6 +740,206 @@ pub fn record_page(on_navigate_to_settings: EventHandler<()>) -> Element {
 
                             rect { height: "10" }
 
+                            // サンプルレート・形式（デバイスが対応していないものはグレー表示）
+                            rect {
+                                direction: "horizontal",
+                                cross_align: "center",
+
+                                label {
+                                    color: "white",
+                                    font_size: "16",
+                                    "{t!(\"sample_rate\")}: "
+                                }
+
+                                Dropdown {
+                                    value: recording_device.sample_rate.to_string(),
+
+                                    for (rate, supported) in device_config::SAMPLE_RATE_CHOICES.map(|rate| {
+                                        (rate, device_config::supports_sample_rate(&recording_device.supported_configs, rate, recording_device.sample_format))
+                                    }) {
+                                        DropdownItem {
+                                            value: rate.to_string(),
+                                            onpress: {
+                                                to_owned![device_idx];
+                                                move |_| {
+                                                    if supported && device_idx < app_state.read().recording_devices.len() {
+                                                        app_state.write().recording_devices[device_idx].sample_rate = rate;
+                                                    }
+                                                }
+                                            },
+                                            if supported {
+                                                label { "{rate} Hz" }
+                                            } else {
+                                                label { color: "rgb(120, 120, 120)", "{rate} Hz" }
+                                            }
+                                        }
+                                    }
+                                }
+
+                                rect { width: "20" }
+
+                                label {
+                                    color: "white",
+                                    font_size: "16",
+                                    "{t!(\"sample_format\")}: "
+                                }
+
+                                Dropdown {
+                                    value: match recording_device.sample_format {
+                                        Some(format) => format.to_string(),
+                                        None => "auto".to_string(),
+                                    },
+
+                                    DropdownItem {
+                                        value: "auto".to_string(),
+                                        onpress: {
+                                            to_owned![device_idx];
+                                            move |_| {
+                                                if device_idx < app_state.read().recording_devices.len() {
+                                                    let mut state = app_state.write();
+                                                    let device = &mut state.recording_devices[device_idx];
+                                                    device.sample_format = None;
+                                                    device.fit_to_supported_configs();
+                                                }
+                                            }
+                                        },
+                                        label { "{t!(\"auto\")}" }
+                                    }
+
+                                    for (format, supported) in device_config::SAMPLE_FORMAT_CHOICES.map(|format| {
+                                        (format, device_config::supports_sample_format(&recording_device.supported_configs, format))
+                                    }) {
+                                        DropdownItem {
+                                            value: format.to_string(),
+                                            onpress: {
+                                                to_owned![device_idx];
+                                                move |_| {
+                                                    if supported && device_idx < app_state.read().recording_devices.len() {
+                                                        let mut state = app_state.write();
+                                                        let device = &mut state.recording_devices[device_idx];
+                                                        device.sample_format = Some(format);
+                                                        // 形式を変えたら、その形式で対応しているレートに合わせる
+                                                        device.fit_to_supported_configs();
+                                                    }
+                                                }
+                                            },
+                                            if supported {
+                                                label { "{format}" }
+                                            } else {
+                                                label { color: "rgb(120, 120, 120)", "{format}" }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+
+                            rect { height: "10" }
+
+                            // ゲイン・コンプレッサー
+                            rect {
+                                direction: "horizontal",
+                                cross_align: "center",
+
+                                label {
+                                    color: "white",
+                                    font_size: "16",
+                                    "{t!(\"gain\")}: "
+                                }
+
+                                Dropdown {
+                                    value: format!("{}", recording_device.gain_db),
+
+                                    for gain_db in [-12.0f32, -6.0, 0.0, 6.0, 12.0] {
+                                        DropdownItem {
+                                            value: format!("{}", gain_db),
+                                            onpress: {
+                                                to_owned![device_idx];
+                                                move |_| {
+                                                    if device_idx < app_state.read().recording_devices.len() {
+                                                        app_state.write().recording_devices[device_idx].gain_db = gain_db;
+                                                    }
+                                                }
+                                            },
+                                            label { "{gain_db:+} dB" }
+                                        }
+                                    }
+                                }
+
+                                rect { width: "20" }
+
+                                label {
+                                    color: "white",
+                                    font_size: "16",
+                                    "{t!(\"compressor\")}: "
+                                }
+
+                                rect {
+                                    background: if recording_device.compressor_enabled { "rgb(0, 120, 255)" } else { "rgb(80, 80, 80)" },
+                                    padding: "8",
+                                    corner_radius: "4",
+
+                                    Button {
+                                        onpress: {
+                                            to_owned![device_idx];
+                                            move |_| {
+                                                if device_idx < app_state.read().recording_devices.len() {
+                                                    let current_state = app_state.read().recording_devices[device_idx].compressor_enabled;
+                                                    app_state.write().recording_devices[device_idx].compressor_enabled = !current_state;
+                                                }
+                                            }
+                                        },
+                                        label {
+                                            if recording_device.compressor_enabled { "{t!(\"enabled\")}" } else { "{t!(\"disabled\")}" }
+                                        }
+                                    }
+                                }
+                            }
+
+                            // 保存されていたデバイスが見つからなかった場合
+                            if let Some(missing_name) = &recording_device.missing_device_name {
+                                rect { height: "5" }
+                                label {
+                                    color: "rgb(255, 200, 100)",
+                                    font_size: "14",
+                                    {t!("device_missing", name: missing_name.clone())}
+                                }
+                            }
+
+                            // モニター出力を開けなかった場合（録音中のみ）
+                            if recording_device.is_recording {
+                                if let Some(monitor_error) = delay_line::monitor_error(device_idx) {
+                                    rect { height: "5" }
+                                    label {
+                                        color: "rgb(255, 200, 100)",
+                                        font_size: "14",
+                                        {t!("monitor_unavailable", reason: monitor_error)}
+                                    }
+                                }
+                            }
+
+                            // 入力の音から推定したラベル（分析を止めたときなど、結果が無ければ表示しない）
+                            {
+                                let device_label = *recording_device.device_label.lock().unwrap();
+                                let analyzing = recording_device.is_recording || recording_device.is_probing();
+                                rsx! {
+                                    if device_label.is_some() || analyzing {
+                                        rect { height: "5" }
+                                        label {
+                                            color: if device_label.is_some_and(DeviceLabel::is_warning) { "rgb(255, 200, 100)" } else { "rgb(180, 180, 180)" },
+                                            font_size: "14",
+                                            {
+                                                match device_label {
+                                                    Some(device_label) => t!("sounds_like", label: t!(device_label.key())),
+                                                    None => t!("analyzing_input"),
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+
+                            rect { height: "10" }
+
                             // 波形表示
                             rect {
                                 width: "100%",
@@ -569,6 +993,17 @@ pub fn record_page(on_navigate_to_settings: EventHandler<()>) -> Element {
                                     font_size: "14",
                                     "🔴 録音中..."
                                 }
+                                label {
+                                    color: "white",
+                                    font_size: "14",
+                                    {
+                                        let elapsed = recording_device.recording_start_time
+                                            .map(|start_time| start_time.elapsed().as_secs())
+                                            .unwrap_or(0);
+                                        let bytes = recording_device.estimated_bytes.load(Ordering::Relaxed);
+                                        format!("{} / {}", format_elapsed(elapsed), format_file_size(bytes))
+                                    }
+                                }
                             }
                         }
                     }
@@ -578,3 +1013,24 @@ pub fn record_page(on_navigate_to_settings: EventHandler<()>) -> Element {
     }
     }
 }
+
+fn format_elapsed(secs: u64) -> String {
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+fn format_file_size(bytes: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = KB * 1024;
+    const GB: u64 = MB * 1024;
+    if bytes >= GB {
+        format!("{:.2} GB", bytes as f64 / GB as f64)
+    } else if bytes >= MB {
+        format!("{:.1} MB", bytes as f64 / MB as f64)
+    } else {
+        format!("{} KB", bytes / KB)
+    }
+}
@@ -0,0 +1,503 @@
+//録音エンジン（GUIとバックグラウンド動作の両方から使う）
+use crate::delay_line::{self, DelayLine};
+use crate::device_config;
+use crate::device_probe::{self, DeviceLabel};
+use crate::effect;
+use crate::event_capture::EventDetector;
+use crate::metrics;
+use crate::setting_page::{AppSettings, AudioFormat};
+use crate::shutdown;
+
+use chrono::Local;
+use cpal::Sample;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use flacenc::{
+    config::Encoder as FlacEncoder, 
+    source::MemSource, 
+    bitsink::ByteSink, 
+    component::BitRepr,
+    error::Verify
+};
+use hound::{WavSpec, WavWriter};
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicU64, Ordering},
+};
+
+/// 1デバイス分の録音の指定
+#[derive(Clone)]
+pub struct RecordOptions {
+    /// 録音ページのカードの番号（メトリクスの区別に使う）
+    pub card_index: usize,
+    pub device_index: usize,
+    pub device_name: String,
+    pub sample_rate: u32,
+    /// Noneなら自動で選ぶ
+    pub sample_format: Option<cpal::SampleFormat>,
+    pub gain_db: f32,
+    pub compressor_enabled: bool,
+}
+
+/// 録音スレッドの本体。`stop_flag` がtrueになるか終了要求が来るまで録音し、ファイルを閉じて戻る。
+/// デバイス・ファイルを開けなかったときは、録音を始めずにErrを返す
+///
+/// - `waveform_data`: 波形表示用に直近のサンプルを書き込む
+/// - `estimated_bytes`: 推定ファイルサイズを加算していく
+/// - `device_label`: 録音の最初の部分の音から推定した、つながっていそうなものを書き込む
+/// - `started`: 録音が始まったときに呼ばれる
+pub fn record(
+    options: RecordOptions,
+    stop_flag: Arc<Mutex<bool>>,
+    waveform_data: Arc<Mutex<Vec<f32>>>,
+    estimated_bytes: Arc<AtomicU64>,
+    device_label: Arc<Mutex<Option<DeviceLabel>>>,
+    started: impl FnOnce(),
+) -> Result<(), String> {
+    let RecordOptions {
+        card_index,
+        device_index: selected_device_index,
+        device_name,
+        sample_rate: requested_sample_rate,
+        sample_format: requested_sample_format,
+        gain_db,
+        compressor_enabled,
+    } = options;
+
+    // ファイルを閉じ終わるまで終了処理を待たせる
+    let _recording_guard = shutdown::begin_recording();
+    let settings = AppSettings::effective();
+    let output_dir = std::path::PathBuf::from(&settings.output_dir);
+    // 空（カレントディレクトリ）の場合は何もしない
+    if let Err(e) = std::fs::create_dir_all(&output_dir) {
+        eprintln!("保存先フォルダ作成エラー: {}", e);
+    }
+    // 一覧を取ってから抜き差しがあっても、選んだデバイスを名前で開く
+    let device = device_config::find_input_device(&device_name, selected_device_index)
+        .ok_or_else(|| format!("デバイスが見つかりません: {}", device_name))?;
+    // デバイスが対応している設定から、指定に一番近いものを選ぶ
+    let supported_configs = device_config::supported_configs(&device);
+    let config = match device_config::choose_config(&supported_configs, requested_sample_rate, requested_sample_format) {
+        Some(config) => config,
+        None => device.default_input_config().map_err(|e| e.to_string())?,
+    };
+    let stream_sample_rate = config.sample_rate().0;
+    let channels = config.channels();
+
+    let now = Local::now();
+    let (filename, writer_opt) = match settings.audio_format {
+        AudioFormat::Wave => {
+            let spec = WavSpec {
+                channels: config.channels(),
+                sample_rate: stream_sample_rate,
+                bits_per_sample: settings.bit_depth,
+                sample_format: hound::SampleFormat::Int,
+            };
+            let filename = output_dir.join(format!("{}-{}.wav",
+                now.format("%Y-%m-%d-%H-%M-%S"),
+                device_name.replace(" ", "_")
+            ));
+            let writer = WavWriter::create(&filename, spec)
+                .map_err(|e| format!("ファイルを作成できません ({}): {}", filename.display(), e))?;
+            (filename, Some(Arc::new(Mutex::new(Some(writer)))))
+        },
+        AudioFormat::Pcm => {
+            let filename = output_dir.join(format!("{}-{}.pcm",
+                now.format("%Y-%m-%d-%H-%M-%S"),
+                device_name.replace(" ", "_")
+            ));
+            (filename, None)
+        },
+        AudioFormat::Flac => {
+            let filename = output_dir.join(format!("{}-{}.flac",
+                now.format("%Y-%m-%d-%H-%M-%S"),
+                device_name.replace(" ", "_")
+            ));
+            (filename, None)
+        },
+    };
+
+    let pcm_file = if matches!(settings.audio_format, AudioFormat::Pcm) {
+        let file = std::fs::File::create(&filename)
+            .map_err(|e| format!("ファイルを作成できません ({}): {}", filename.display(), e))?;
+        Some(Arc::new(Mutex::new(file)))
+    } else {
+        None
+    };
+
+    // FLAC用のサンプルバッファ
+    let flac_samples = if matches!(settings.audio_format, AudioFormat::Flac) {
+        Some(Arc::new(Mutex::new(Vec::<i32>::new())))
+    } else {
+        None
+    };
+
+    let device_metrics = metrics::register_device(card_index, &device_name);
+    // 途中で異常終了しても recording=1 のまま残らないようにする
+    let _metrics_guard = metrics::finish_on_drop(&device_metrics);
+    let metrics_err = device_metrics.clone();
+    let err_fn = move |err: cpal::StreamError| {
+        metrics_err.dropouts.fetch_add(1, Ordering::Relaxed);
+        eprintln!("録音エラー: {:?}", err);
+    };
+    let metrics_stream = device_metrics.clone();
+    let writer_clone = writer_opt.clone();
+    let pcm_file_clone = pcm_file.clone();
+    let flac_samples_clone = flac_samples.clone();
+    let stop_flag_stream = Arc::clone(&stop_flag);
+    let waveform_clone = waveform_data.clone();
+    let format = settings.audio_format.clone();
+    // ファイルサイズ推定用の1サンプルあたりのバイト数（FLACは非圧縮換算）
+    let bytes_per_sample = match settings.audio_format {
+        AudioFormat::Pcm => 2,
+        AudioFormat::Wave | AudioFormat::Flac => settings.bit_depth as u64 / 8,
+    };
+    
+    let gain = 10f32.powf(gain_db / 20.0);
+
+    // コンプレッサー設定をローカル変数にコピー（有効/無効はデバイスごと）
+    // let compressor_threshold_db = settings.compressor_threshold_db;
+    // let compressor_ratio = settings.compressor_ratio;
+    let compressor_threshold_db:f32 = -20.0;
+    let compressor_ratio:f32 = 4.0;
+
+    // イベント検出（設定で有効な場合）
+    let event_detector = if settings.event_capture_enabled {
+        Some(Arc::new(Mutex::new(EventDetector::new(
+            settings.event_threshold_db,
+            settings.event_min_duration_ms,
+            settings.event_padding_ms,
+            config.channels(),
+            stream_sample_rate,
+            device_name.clone(),
+            &output_dir,
+        ))))
+    } else {
+        None
+    };
+    let event_detector_clone = event_detector.clone();
+
+    // モニター出力（設定で有効な場合）。出力ストリームは録音が終わるまで持っておく
+    // 使えなかった理由はカードに表示する
+    let monitor = if settings.monitor_enabled {
+        match start_monitor(&settings, config.channels(), stream_sample_rate) {
+            Ok(monitor) => {
+                delay_line::set_monitor_error(card_index, None);
+                Some(monitor)
+            }
+            Err(e) => {
+                eprintln!("モニター出力を使えません: {}", e);
+                delay_line::set_monitor_error(card_index, Some(e));
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let monitor_line = monitor.as_ref().map(|(_, delay_line)| delay_line.clone());
+
+    // 録音を始めたときの入力を分析するため、先頭チャンネルを少しだけためておく
+    let probe_length = device_probe::probe_length(stream_sample_rate);
+    let probe_samples = Arc::new(Mutex::new(Vec::with_capacity(probe_length)));
+    let probe_samples_clone = probe_samples.clone();
+
+    let on_data = move |data: &[f32]| {
+        if *stop_flag_stream.lock().unwrap() {
+            return;
+        }
+
+        {
+            let mut probe_samples = probe_samples_clone.lock().unwrap();
+            if probe_samples.len() < probe_length {
+                probe_samples.extend(data.iter().step_by(channels.max(1) as usize));
+            }
+        }
+
+        // ゲインを適用
+        let gained_data: Vec<f32> = if gain_db != 0.0 {
+            data.iter().map(|&sample| (sample * gain).clamp(-1.0, 1.0)).collect()
+        } else {
+            data.to_vec()
+        };
+
+        // コンプレッサーを適用（デバイスで有効な場合）
+        let processed_data = if compressor_enabled {
+            effect::compress_audio(
+                &gained_data,
+                compressor_threshold_db,
+                compressor_ratio
+            )
+        } else {
+            gained_data
+        };
+
+        // 波形データを更新
+        {
+            let mut waveform = waveform_clone.lock().unwrap();
+            waveform.clear();
+            waveform.extend_from_slice(&processed_data);
+            if waveform.len() > 300 {
+                let len = waveform.len();
+                waveform.drain(0..len-300);
+            }
+        }
+
+        estimated_bytes.fetch_add(processed_data.len() as u64 * bytes_per_sample, Ordering::Relaxed);
+        metrics_stream.set_peak_level(
+            processed_data.iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+        );
+        metrics_stream.samples_written.fetch_add(processed_data.len() as u64, Ordering::Relaxed);
+
+        if let Some(ref detector_arc) = event_detector_clone {
+            detector_arc.lock().unwrap().process(&processed_data);
+        }
+
+        if let Some(ref delay_line) = monitor_line {
+            delay_line.lock().unwrap().push(&processed_data);
+        }
+
+        // フォーマットに応じてデータを書き込み
+        match format {
+            AudioFormat::Wave => {
+                if let Some(ref writer_arc) = writer_clone {
+                    let mut writer_lock = writer_arc.lock().unwrap();
+                    if let Some(writer) = writer_lock.as_mut() {
+                        for &sample in &processed_data {
+                            let sample_i16 = (sample * i16::MAX as f32) as i16;
+                            writer.write_sample(sample_i16).unwrap();
+                        }
+                    }
+                }
+            },
+            AudioFormat::Pcm => {
+                if let Some(ref pcm_file_arc) = pcm_file_clone {
+                    use std::io::Write;
+                    let mut file = pcm_file_arc.lock().unwrap();
+                    for &sample in &processed_data {
+                        let sample_i16 = (sample * i16::MAX as f32) as i16;
+                        file.write_all(&sample_i16.to_le_bytes()).unwrap();
+                    }
+                }
+            },
+            AudioFormat::Flac => {
+                if let Some(ref flac_samples_arc) = flac_samples_clone {
+                    let mut samples = flac_samples_arc.lock().unwrap();
+                    for &sample in &processed_data {
+                        let sample_i32 = (sample * i32::MAX as f32) as i32;
+                        samples.push(sample_i32);
+                    }
+                    metrics_stream.queue_depth.store(samples.len() as u64, Ordering::Relaxed);
+                }
+            },
+        }
+    };
+
+    let sample_format = config.sample_format();
+    let stream_config: cpal::StreamConfig = config.into();
+    let stream = match sample_format {
+        cpal::SampleFormat::F32 => build_input_stream::<f32>(&device, &stream_config, on_data, err_fn),
+        cpal::SampleFormat::I32 => build_input_stream::<i32>(&device, &stream_config, on_data, err_fn),
+        cpal::SampleFormat::I16 => build_input_stream::<i16>(&device, &stream_config, on_data, err_fn),
+        cpal::SampleFormat::U16 => build_input_stream::<u16>(&device, &stream_config, on_data, err_fn),
+        format => return Err(format!("対応していないサンプル形式です: {}", format)),
+    }
+    .map_err(|e| e.to_string())?;
+
+    stream.play().map_err(|e| e.to_string())?;
+    started();
+    let mut labeled = false;
+    while !*stop_flag.lock().unwrap() && !shutdown::is_requested() {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        // 重い計算なので、音声のコールバックではなくこちらで分析する
+        if !labeled && probe_samples.lock().unwrap().len() >= probe_length {
+            let samples = probe_samples.lock().unwrap().clone();
+            *device_label.lock().unwrap() = Some(device_probe::classify(&samples, stream_sample_rate));
+            labeled = true;
+        }
+    }
+    drop(stream);
+    drop(monitor);
+    device_metrics.recording.store(false, Ordering::Relaxed);
+
+    if let Some(detector_arc) = event_detector {
+        detector_arc.lock().unwrap().finish();
+    }
+
+    // フォーマットに応じてファイルを閉じる
+    match settings.audio_format {
+        AudioFormat::Wave => {
+            if let Some(writer) = writer_opt.and_then(|writer_arc| writer_arc.lock().unwrap().take()) {
+                if let Err(e) = writer.finalize() {
+                    eprintln!("WAVファイル書き込みエラー: {}", e);
+                }
+            }
+        },
+        AudioFormat::Pcm => {
+            // PCMファイルは自動的に閉じられる
+        },
+        AudioFormat::Flac => {
+            if let Some(flac_samples_arc) = flac_samples {
+                let samples = flac_samples_arc.lock().unwrap();
+                if !samples.is_empty() && shutdown::is_hurried() {
+                    // エンコードが終わる前に強制終了させられるので、すぐ書き終わるWAVで保存する
+                    let wav_filename = filename.with_extension("wav");
+                    if let Err(e) = write_wav_fallback(&wav_filename, &samples, channels, stream_sample_rate) {
+                        eprintln!("WAVファイル書き込みエラー: {}", e);
+                    }
+                } else if !samples.is_empty() {
+                    // FLACエンコーディング
+                    let config = FlacEncoder::default().into_verified().unwrap();
+                    let source = MemSource::from_samples(
+                        &samples,
+                        1,  // モノラルとして扱う
+                        settings.bit_depth as usize,
+                        stream_sample_rate as usize,
+                    );
+                    
+                    match flacenc::encode_with_fixed_block_size(
+                        &config, source, config.block_size
+                    ) {
+                        Ok(flac_stream) => {
+                            let mut sink = ByteSink::new();
+                            if flac_stream.write(&mut sink).is_ok() {
+                                if let Err(e) = std::fs::write(&filename, sink.as_slice()) {
+                                    eprintln!("FLACファイル書き込みエラー: {}", e);
+                                }
+                            } else {
+                                eprintln!("FLACストリーム書き込みエラー");
+                            }
+                        },
+                        Err(e) => eprintln!("FLACエンコードエラー: {}", e),
+                    }
+                }
+            }
+        },
+    }
+    Ok(())
+}
+
+// FLAC用に貯めたサンプルを16bitのWAVとして書き出す
+fn write_wav_fallback(
+    filename: &std::path::Path,
+    samples: &[i32],
+    channels: u16,
+    sample_rate: u32,
+) -> Result<(), hound::Error> {
+    let spec = WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = WavWriter::create(filename, spec)?;
+    for &sample in samples {
+        writer.write_sample((sample >> 16) as i16)?;
+    }
+    writer.finalize()
+}
+
+// モニター出力を開いて、入力を遅延させて流す。
+// 出力のチャンネル数・サンプルレートは、入力と同じものが使えなければ出力デバイスに合わせて変換する
+fn start_monitor(
+    settings: &AppSettings,
+    channels: u16,
+    sample_rate: u32,
+) -> Result<(cpal::Stream, Arc<Mutex<DelayLine>>), String> {
+    let host = cpal::default_host();
+    // 出力先が空なら既定の出力デバイス（仮想ケーブルなどを使う場合は名前で指定する）
+    let device = if settings.monitor_device.is_empty() {
+        host.default_output_device()
+    } else {
+        host.output_devices()
+            .map_err(|e| e.to_string())?
+            .find(|device| device.name().is_ok_and(|name| name == settings.monitor_device))
+    };
+    let device = device.ok_or_else(|| format!("出力デバイスが見つかりません: {}", settings.monitor_device))?;
+
+    // 同じチャンネル数 > 同じサンプルレート > 形式の優先度 で選ぶ。どれも無ければデバイスの既定の設定
+    let supported: Vec<_> = device
+        .supported_output_configs()
+        .map_err(|e| e.to_string())?
+        .filter(|c| device_config::SAMPLE_FORMAT_CHOICES.contains(&c.sample_format()))
+        .filter(|c| c.min_sample_rate().0 <= sample_rate && sample_rate <= c.max_sample_rate().0)
+        .collect();
+    let config = match supported.iter().min_by_key(|c| {
+        let format_rank = device_config::SAMPLE_FORMAT_CHOICES
+            .iter()
+            .position(|format| *format == c.sample_format());
+        (c.channels() != channels, format_rank)
+    }) {
+        Some(c) => c.with_sample_rate(cpal::SampleRate(sample_rate)),
+        None => device.default_output_config().map_err(|e| e.to_string())?,
+    };
+
+    let delay_line = Arc::new(Mutex::new(DelayLine::new(
+        settings.monitor_delay_ms,
+        channels,
+        sample_rate,
+        config.channels(),
+        config.sample_rate().0,
+    )));
+    let delay_line_clone = delay_line.clone();
+    let on_data = move |out: &mut [f32]| delay_line_clone.lock().unwrap().pull(out);
+    let err_fn = |err: cpal::StreamError| eprintln!("モニター出力エラー: {:?}", err);
+
+    let sample_format = config.sample_format();
+    let stream_config: cpal::StreamConfig = config.into();
+    let stream = match sample_format {
+        cpal::SampleFormat::F32 => build_output_stream::<f32>(&device, &stream_config, on_data, err_fn),
+        cpal::SampleFormat::I32 => build_output_stream::<i32>(&device, &stream_config, on_data, err_fn),
+        cpal::SampleFormat::I16 => build_output_stream::<i16>(&device, &stream_config, on_data, err_fn),
+        cpal::SampleFormat::U16 => build_output_stream::<u16>(&device, &stream_config, on_data, err_fn),
+        format => return Err(format!("対応していないサンプル形式です: {}", format)),
+    }
+    .map_err(|e| e.to_string())?;
+    stream.play().map_err(|e| e.to_string())?;
+    Ok((stream, delay_line))
+}
+
+// f32で作った出力をデバイスのサンプル形式に変換する
+fn build_output_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut on_data: impl FnMut(&mut [f32]) + Send + 'static,
+    err_fn: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::SizedSample + cpal::FromSample<f32>,
+{
+    let mut buffer = Vec::new();
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            buffer.resize(data.len(), 0.0f32);
+            on_data(&mut buffer);
+            for (out, &sample) in data.iter_mut().zip(buffer.iter()) {
+                *out = sample.to_sample::<T>();
+            }
+        },
+        err_fn,
+        None,
+    )
+}
+
+// 入力サンプルをf32に変換してから処理する
+pub fn build_input_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut on_data: impl FnMut(&[f32]) + Send + 'static,
+    err_fn: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::SizedSample,
+    f32: cpal::FromSample<T>,
+{
+    device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            let converted: Vec<f32> = data.iter().map(|&sample| sample.to_sample::<f32>()).collect();
+            on_data(&converted);
+        },
+        err_fn,
+        None,
+    )
+}
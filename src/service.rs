@@ -0,0 +1,209 @@
+//バックグラウンド録音（--daemon）をOSの起動時に動かすための登録
+//
+// `rokuon-kun --install-service` / `--uninstall-service` で登録・解除する。
+// - Windows: タスクスケジューラに「起動時に実行」のタスクとして、今のユーザーで登録する
+//   （パスワードは保存せず、ログオンしていなくても動く。登録には管理者権限が必要）
+// - macOS: launchdのLaunchDaemonとして、今のユーザーで動くように登録する
+//   （LaunchAgentはログインするまで動かないため。登録には `sudo` が必要）
+// - それ以外: systemdのユーザーサービスを作る（ログアウト中も動かすには `loginctl enable-linger`）
+#[cfg(not(any(windows, target_os = "macos")))]
+use std::path::PathBuf;
+use std::process::Command;
+
+const SERVICE_NAME: &str = "rokuon-kun";
+
+/// 登録する。戻り値は利用者に表示するメッセージ
+pub fn install() -> Result<String, String> {
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    platform::install(&exe)
+}
+
+/// 登録を解除する。戻り値は利用者に表示するメッセージ
+pub fn uninstall() -> Result<String, String> {
+    platform::uninstall()
+}
+
+fn run(command: &mut Command) -> Result<(), String> {
+    let output = command.output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn home_dir() -> Result<PathBuf, String> {
+    std::env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+        .ok_or_else(|| "HOME が設定されていません".to_string())
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::{SERVICE_NAME, run};
+    use std::path::Path;
+    use std::process::Command;
+
+    pub fn install(exe: &Path) -> Result<String, String> {
+        let user = match (std::env::var("USERDOMAIN"), std::env::var("USERNAME")) {
+            (Ok(domain), Ok(name)) => format!("{}\\{}", domain, name),
+            (_, Ok(name)) => name,
+            _ => return Err("ユーザー名を取得できません".to_string()),
+        };
+        run(Command::new("schtasks").args([
+            "/Create",
+            "/F",
+            "/TN",
+            SERVICE_NAME,
+            "/SC",
+            "ONSTART",
+            "/RU",
+            &user,
+            "/NP",
+            "/TR",
+            &format!("\"{}\" --daemon", exe.display()),
+        ]))?;
+        Ok(format!("タスク「{}」を登録しました（次回の起動時から動きます）", SERVICE_NAME))
+    }
+
+    pub fn uninstall() -> Result<String, String> {
+        run(Command::new("schtasks").args(["/Delete", "/F", "/TN", SERVICE_NAME]))?;
+        Ok(format!("タスク「{}」を削除しました", SERVICE_NAME))
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::{SERVICE_NAME, run};
+    use std::ffi::{CStr, CString};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    fn label() -> String {
+        format!("com.{}.daemon", SERVICE_NAME)
+    }
+
+    fn plist_path() -> PathBuf {
+        Path::new("/Library/LaunchDaemons").join(format!("{}.plist", label()))
+    }
+
+    // sudoで実行されたときは、sudoする前のユーザーで動かす
+    fn user_name() -> Result<String, String> {
+        std::env::var("SUDO_USER")
+            .ok()
+            .filter(|user| !user.is_empty() && user != "root")
+            .or_else(|| std::env::var("USER").ok().filter(|user| !user.is_empty()))
+            .ok_or_else(|| "ユーザー名を取得できません".to_string())
+    }
+
+    // sudoではHOMEが変わっていることがあるので、ユーザーのデータベースから調べる
+    fn user_home(user: &str) -> Result<String, String> {
+        let name = CString::new(user).map_err(|e| e.to_string())?;
+        let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+        if passwd.is_null() {
+            return Err(format!("ユーザー {} が見つかりません", user));
+        }
+        let home = unsafe { CStr::from_ptr((*passwd).pw_dir) };
+        Ok(home.to_string_lossy().into_owned())
+    }
+
+    fn escape_xml(value: &str) -> String {
+        value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+    }
+
+    pub fn install(exe: &Path) -> Result<String, String> {
+        let path = plist_path();
+        let user = user_name()?;
+        let home = user_home(&user)?;
+        // UserNameで指定したユーザーで動かし、設定ファイルはそのユーザーのものを使う
+        let plist = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{}</string>
+        <string>--daemon</string>
+    </array>
+    <key>UserName</key>
+    <string>{}</string>
+    <key>EnvironmentVariables</key>
+    <dict>
+        <key>HOME</key>
+        <string>{}</string>
+    </dict>
+    <key>RunAtLoad</key>
+    <true/>
+</dict>
+</plist>
+"#,
+            label(),
+            escape_xml(&exe.display().to_string()),
+            escape_xml(&user),
+            escape_xml(&home)
+        );
+        fs::write(&path, plist).map_err(|e| format!("{} ({}、sudoで実行してください)", e, path.display()))?;
+        run(Command::new("launchctl").arg("load").arg(&path))?;
+        Ok(format!(
+            "{} を登録しました（{} で動きます。マイクへのアクセスを一度GUIで許可しておいてください）",
+            path.display(),
+            user
+        ))
+    }
+
+    pub fn uninstall() -> Result<String, String> {
+        let path = plist_path();
+        run(Command::new("launchctl").arg("unload").arg(&path))?;
+        fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(format!("{} を削除しました", path.display()))
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+mod platform {
+    use super::{SERVICE_NAME, home_dir, run};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    fn unit_path() -> Result<PathBuf, String> {
+        let config_dir = match std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => home_dir()?.join(".config"),
+        };
+        Ok(config_dir.join("systemd/user").join(format!("{}.service", SERVICE_NAME)))
+    }
+
+    pub fn install(exe: &Path) -> Result<String, String> {
+        let path = unit_path()?;
+        let unit = format!(
+            "[Unit]\nDescription=rokuon-kun background recording\n\n\
+             [Service]\nExecStart=\"{exe}\" --daemon\nExecStop=\"{exe}\" --ctl quit\n\n\
+             [Install]\nWantedBy=default.target\n",
+            exe = exe.display()
+        );
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        fs::write(&path, unit).map_err(|e| e.to_string())?;
+        run(Command::new("systemctl").args(["--user", "daemon-reload"]))?;
+        run(Command::new("systemctl").args(["--user", "enable", "--now", SERVICE_NAME]))?;
+        Ok(format!(
+            "{} を登録しました（ログアウト中も動かすには `loginctl enable-linger` を実行してください）",
+            path.display()
+        ))
+    }
+
+    pub fn uninstall() -> Result<String, String> {
+        let path = unit_path()?;
+        run(Command::new("systemctl").args(["--user", "disable", "--now", SERVICE_NAME]))?;
+        fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(format!("{} を削除しました", path.display()))
+    }
+}
@@ -1,19 +1,191 @@
 use freya::prelude::*;
-use nojson::{DisplayJson, Json, JsonFormatter, JsonParseError, RawJsonValue, json};
+use nojson::{DisplayJson, Json, JsonFormatter, JsonParseError, RawJson, RawJsonValue, json};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::Path;
+use std::sync::Mutex;
+use chrono::Local;
 use dioxus_i18n::{prelude::*, t};
 use crate::i18n::Language;
+use crate::overrides;
+use crate::config_path;
+
+/// 設定ファイルのスキーマバージョン。項目の意味や形式を変えたら上げて、
+/// `MIGRATIONS` に変換処理を追加する
+pub const SETTINGS_VERSION: u32 = 2;
+
+/// 型付きで読み込む前の設定ファイル（トップレベルのキー → 値のJSONテキスト）
+type RawSettings = BTreeMap<String, String>;
+
+/// `MIGRATIONS[n]` はバージョン n から n + 1 への変換。
+/// `AppSettings` として読む前の生のJSONを書き換えるので、キー名や型を変えても古いファイルを読める
+const MIGRATIONS: [fn(&mut RawSettings); SETTINGS_VERSION as usize] = [
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+];
+
+// v0: versionフィールドがない頃の形式。項目はそのまま使える
+fn migrate_v0_to_v1(_settings: &mut RawSettings) {}
+
+// v1: compressor_enabled は全ての録音にかかっていた
+// v2: compressor_enabled は新しいカードの初期値になり、カードごとに保存する。
+//     v1の動作を保つため、保存されているカードのうち値の無いものに写す
+fn migrate_v1_to_v2(settings: &mut RawSettings) {
+    let (Some(compressor_enabled), Some(devices)) =
+        (settings.get("compressor_enabled").cloned(), settings.get("devices").cloned())
+    else {
+        return;
+    };
+    let Ok(json) = RawJson::parse(&devices) else {
+        return;
+    };
+    let Ok(items) = json.value().to_array() else {
+        return;
+    };
+
+    let devices: Vec<String> = items
+        .map(|item| match raw_members(item) {
+            Ok(mut device) => {
+                device
+                    .entry("compressor_enabled".to_string())
+                    .or_insert_with(|| compressor_enabled.clone());
+                raw_object_text(&device)
+            }
+            Err(_) => item.as_raw_str().to_string(),
+        })
+        .collect();
+    settings.insert("devices".to_string(), format!("[{}]", devices.join(",")));
+}
+
+// JSONのオブジェクトを、項目ごとの値のJSONテキストに分ける
+fn raw_members(value: RawJsonValue<'_, '_>) -> Result<RawSettings, String> {
+    let mut members = RawSettings::new();
+    for (key, value) in value.to_object().map_err(|e| e.to_string())? {
+        let key = key.to_unquoted_string_str().map_err(|e| e.to_string())?;
+        members.insert(key.into_owned(), value.as_raw_str().to_string());
+    }
+    Ok(members)
+}
+
+fn raw_object_text(members: &RawSettings) -> String {
+    let members: Vec<String> = members
+        .iter()
+        .map(|(key, value)| format!("{}:{}", Json(key), value))
+        .collect();
+    format!("{{{}}}", members.join(","))
+}
+
+/// 起動時の設定読み込みで起きたことをユーザーに知らせるための通知
+#[derive(Clone, PartialEq)]
+pub enum SettingsNotice {
+    Migrated { from_version: u32, backup_path: Option<String> },
+    LoadFailed { error: String, backup_path: Option<String> },
+}
+
+static SETTINGS_NOTICE: Mutex<Option<SettingsNotice>> = Mutex::new(None);
+
+/// 起動時の通知を取り出す（1回だけ表示するため）
+pub fn take_settings_notice() -> Option<SettingsNotice> {
+    SETTINGS_NOTICE.lock().unwrap().take()
+}
 
 #[derive(Clone, PartialEq)]
 pub struct AppSettings {
     pub audio_format: AudioFormat,
     pub sample_rate: u32,
     pub bit_depth: u16,
+    /// 録音ファイルの保存先（空ならカレントディレクトリ）
+    pub output_dir: String,
     pub compressor_enabled: bool,
     pub compressor_threshold_db: f32,
     pub compressor_ratio: f32,
+    pub event_capture_enabled: bool,
+    pub event_threshold_db: f32,
+    pub event_min_duration_ms: u32,
+    pub event_padding_ms: u32,
+    pub metrics_enabled: bool,
+    pub metrics_port: u16,
+    /// バックグラウンド録音（--daemon）の制御用ポート
+    pub daemon_port: u16,
+    /// 制御用ポートで待ち受けるアドレス（--ctl の接続先にもなる）。127.0.0.1 以外はTLSが必要
+    pub daemon_address: String,
+    /// 制御通信のTLS証明書・秘密鍵のPEMファイル（空ならTLSを使わない）
+    pub daemon_tls_cert: String,
+    pub daemon_tls_key: String,
+    /// 入力を遅らせてモニター出力に流す（放送用ディレイ）
+    pub monitor_enabled: bool,
+    pub monitor_delay_ms: u32,
+    /// モニター出力デバイス名（空なら既定の出力デバイス）
+    pub monitor_device: String,
     pub language: Language,
+    /// 録音ページのデバイス構成（起動時に復元する）
+    pub devices: Vec<DeviceSetup>,
+}
+
+/// 録音ページのデバイス1つ分の設定
+#[derive(Clone, PartialEq)]
+pub struct DeviceSetup {
+    pub device_name: String,
+    pub gain_db: f32,
+    pub compressor_enabled: bool,
+    pub sample_rate: u32,
+    /// "f32" などのcpalのサンプル形式名。"auto"なら自動
+    pub sample_format: String,
+}
+
+impl DisplayJson for DeviceSetup {
+    fn fmt(&self, f: &mut JsonFormatter<'_, '_>) -> std::fmt::Result {
+        f.object(|f| {
+            f.member("device_name", self.device_name.as_str())?;
+            f.member("gain_db", self.gain_db)?;
+            f.member("compressor_enabled", self.compressor_enabled)?;
+            f.member("sample_rate", self.sample_rate)?;
+            f.member("sample_format", self.sample_format.as_str())
+        })
+    }
+}
+
+impl<'text, 'raw> TryFrom<RawJsonValue<'text, 'raw>> for DeviceSetup {
+    type Error = JsonParseError;
+
+    fn try_from(value: RawJsonValue<'text, 'raw>) -> Result<Self, Self::Error> {
+        let device_name = value.to_member("device_name")?.required()?.try_into()?;
+        let gain_db = match value.to_member("gain_db") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or(0.0),
+                Err(_) => 0.0,
+            },
+            Err(_) => 0.0,
+        };
+        let compressor_enabled = match value.to_member("compressor_enabled") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or(false),
+                Err(_) => false,
+            },
+            Err(_) => false,
+        };
+        let sample_rate = match value.to_member("sample_rate") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or(44100),
+                Err(_) => 44100,
+            },
+            Err(_) => 44100,
+        };
+        let sample_format = match value.to_member("sample_format") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or("auto".to_string()),
+                Err(_) => "auto".to_string(),
+            },
+            Err(_) => "auto".to_string(),
+        };
+
+        Ok(DeviceSetup {
+            device_name,
+            gain_db,
+            compressor_enabled,
+            sample_rate,
+            sample_format,
+        })
+    }
 }
 
 #[derive(Clone, PartialEq)]
@@ -27,6 +199,7 @@ pub enum AudioFormat {
 impl DisplayJson for AppSettings {
     fn fmt(&self, f: &mut JsonFormatter<'_, '_>) -> std::fmt::Result {
         f.object(|f| {
+            f.member("version", SETTINGS_VERSION)?;
             f.member(
                 "audio_format",
                 match self.audio_format {
@@ -37,13 +210,28 @@ impl DisplayJson for AppSettings {
             )?;
             f.member("sample_rate", self.sample_rate)?;
             f.member("bit_depth", self.bit_depth)?;
+            f.member("output_dir", self.output_dir.as_str())?;
             f.member("compressor_enabled", self.compressor_enabled)?;
             f.member("compressor_threshold_db", self.compressor_threshold_db)?;
             f.member("compressor_ratio", self.compressor_ratio)?;
+            f.member("event_capture_enabled", self.event_capture_enabled)?;
+            f.member("event_threshold_db", self.event_threshold_db)?;
+            f.member("event_min_duration_ms", self.event_min_duration_ms)?;
+            f.member("event_padding_ms", self.event_padding_ms)?;
+            f.member("metrics_enabled", self.metrics_enabled)?;
+            f.member("metrics_port", self.metrics_port)?;
+            f.member("daemon_port", self.daemon_port)?;
+            f.member("daemon_address", &self.daemon_address)?;
+            f.member("daemon_tls_cert", &self.daemon_tls_cert)?;
+            f.member("daemon_tls_key", &self.daemon_tls_key)?;
+            f.member("monitor_enabled", self.monitor_enabled)?;
+            f.member("monitor_delay_ms", self.monitor_delay_ms)?;
+            f.member("monitor_device", &self.monitor_device)?;
             f.member("language", match self.language {
                 Language::Japanese => "ja",
                 Language::English => "en",
-            })
+            })?;
+            f.member("devices", &self.devices)
         })
     }
 }
@@ -63,6 +251,15 @@ impl<'text, 'raw> TryFrom<RawJsonValue<'text, 'raw>> for AppSettings {
 
         let sample_rate = value.to_member("sample_rate")?.required()?.try_into()?;
         let bit_depth = value.to_member("bit_depth")?.required()?.try_into()?;
+
+        // 保存先（オプション、デフォルトはカレントディレクトリ）
+        let output_dir = match value.to_member("output_dir") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or_default(),
+                Err(_) => String::new(),
+            },
+            Err(_) => String::new(),
+        };
         
         // コンプレッサー設定（オプション、デフォルト値あり）
         let compressor_enabled = match value.to_member("compressor_enabled") {
@@ -87,6 +284,105 @@ impl<'text, 'raw> TryFrom<RawJsonValue<'text, 'raw>> for AppSettings {
             Err(_) => 4.0,
         };
 
+        // イベント検出設定（オプション、デフォルト値あり）
+        let event_capture_enabled = match value.to_member("event_capture_enabled") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or(false),
+                Err(_) => false,
+            },
+            Err(_) => false,
+        };
+        let event_threshold_db = match value.to_member("event_threshold_db") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or(-20.0),
+                Err(_) => -20.0,
+            },
+            Err(_) => -20.0,
+        };
+        let event_min_duration_ms = match value.to_member("event_min_duration_ms") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or(200),
+                Err(_) => 200,
+            },
+            Err(_) => 200,
+        };
+        let event_padding_ms = match value.to_member("event_padding_ms") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or(2000),
+                Err(_) => 2000,
+            },
+            Err(_) => 2000,
+        };
+
+        // メトリクス設定（オプション、デフォルト値あり）
+        let metrics_enabled = match value.to_member("metrics_enabled") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or(false),
+                Err(_) => false,
+            },
+            Err(_) => false,
+        };
+        let metrics_port = match value.to_member("metrics_port") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or(9898),
+                Err(_) => 9898,
+            },
+            Err(_) => 9898,
+        };
+
+        // バックグラウンド録音設定（オプション、デフォルト値あり）
+        let daemon_port = match value.to_member("daemon_port") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or(9899),
+                Err(_) => 9899,
+            },
+            Err(_) => 9899,
+        };
+        let daemon_address = match value.to_member("daemon_address") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or_else(|_| "127.0.0.1".to_string()),
+                Err(_) => "127.0.0.1".to_string(),
+            },
+            Err(_) => "127.0.0.1".to_string(),
+        };
+        let daemon_tls_cert = match value.to_member("daemon_tls_cert") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or_default(),
+                Err(_) => String::new(),
+            },
+            Err(_) => String::new(),
+        };
+        let daemon_tls_key = match value.to_member("daemon_tls_key") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or_default(),
+                Err(_) => String::new(),
+            },
+            Err(_) => String::new(),
+        };
+
+        // モニター出力設定（オプション、デフォルト値あり）
+        let monitor_enabled = match value.to_member("monitor_enabled") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or(false),
+                Err(_) => false,
+            },
+            Err(_) => false,
+        };
+        let monitor_delay_ms = match value.to_member("monitor_delay_ms") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or(7000),
+                Err(_) => 7000,
+            },
+            Err(_) => 7000,
+        };
+        let monitor_device = match value.to_member("monitor_device") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or_default(),
+                Err(_) => String::new(),
+            },
+            Err(_) => String::new(),
+        };
+
         // 言語設定（オプション、デフォルト値あり）
         let language = match value.to_member("language") {
             Ok(member) => match member.required() {
@@ -102,14 +398,38 @@ impl<'text, 'raw> TryFrom<RawJsonValue<'text, 'raw>> for AppSettings {
             Err(_) => Language::Japanese,
         };
 
+        // デバイス構成（オプション、デフォルトは空）
+        let devices = match value.to_member("devices") {
+            Ok(member) => match member.required() {
+                Ok(val) => val.try_into().unwrap_or_default(),
+                Err(_) => Vec::new(),
+            },
+            Err(_) => Vec::new(),
+        };
+
         Ok(AppSettings {
             audio_format,
             sample_rate,
             bit_depth,
+            output_dir,
             compressor_enabled,
             compressor_threshold_db,
             compressor_ratio,
+            event_capture_enabled,
+            event_threshold_db,
+            event_min_duration_ms,
+            event_padding_ms,
+            metrics_enabled,
+            metrics_port,
+            daemon_port,
+            daemon_address,
+            daemon_tls_cert,
+            daemon_tls_key,
+            monitor_enabled,
+            monitor_delay_ms,
+            monitor_device,
             language,
+            devices,
         })
     }
 }
@@ -121,26 +441,151 @@ impl Default for AppSettings {
             audio_format: AudioFormat::Wave,
             sample_rate: 44100,
             bit_depth: 16,
+            output_dir: String::new(),
             compressor_enabled: false,
             compressor_threshold_db: -20.0,
             compressor_ratio: 4.0,
+            event_capture_enabled: false,
+            event_threshold_db: -20.0,
+            event_min_duration_ms: 200,
+            event_padding_ms: 2000,
+            metrics_enabled: false,
+            metrics_port: 9898,
+            daemon_port: 9899,
+            daemon_address: "127.0.0.1".to_string(),
+            daemon_tls_cert: String::new(),
+            daemon_tls_key: String::new(),
+            monitor_enabled: false,
+            monitor_delay_ms: 7000,
+            monitor_device: String::new(),
             language: Language::Japanese,
+            devices: Vec::new(),
         }
     }
 }
 
 impl AppSettings {
+    /// 設定を読み込む。ファイルには手を加えない。読み込めなければエラーを出して既定値を使う
     pub fn load() -> Self {
-        if Path::new("settings.json").exists() {
-            match fs::read_to_string("settings.json") {
-                Ok(content) => match content.parse::<Json<AppSettings>>() {
-                    Ok(settings) => settings.0,
-                    Err(_) => Self::default(),
-                },
-                Err(_) => Self::default(),
-            }
-        } else {
+        Self::try_load().unwrap_or_else(|e| {
+            eprintln!("設定の読み込みエラー（既定値を使います）: {}", e);
             Self::default()
+        })
+    }
+
+    /// 設定を読み込む。ファイルが無ければ既定値、読み込めなければエラー
+    pub fn try_load() -> Result<Self, String> {
+        Ok(Self::read_and_migrate()?
+            .map(|(_, settings)| settings)
+            .unwrap_or_default())
+    }
+
+    /// settings.jsonにコマンドライン引数・環境変数の上書きを適用した、今回の実行で使う設定
+    pub fn effective() -> Self {
+        let mut settings = Self::load();
+        overrides::apply(&mut settings);
+        settings
+    }
+
+    /// 起動時に1回だけ呼ぶ。古いバージョンの設定はバックアップしてから変換・保存し、
+    /// 読み込めない設定はバックアップを残して既定値を使う
+    pub fn load_and_migrate() -> Self {
+        Self::import_legacy_file();
+
+        let (settings, notice) = match Self::read_and_migrate() {
+            Ok(None) => (Self::default(), None),
+            Ok(Some((version, settings))) if version == SETTINGS_VERSION => (settings, None),
+            Ok(Some((version, settings))) => {
+                let backup_path = Self::backup_file();
+                if let Err(e) = settings.save() {
+                    eprintln!("設定の保存エラー: {}", e);
+                }
+                (settings, Some(SettingsNotice::Migrated {
+                    from_version: version,
+                    backup_path,
+                }))
+            }
+            Err(error) => (Self::default(), Some(SettingsNotice::LoadFailed {
+                error,
+                backup_path: Self::backup_file(),
+            })),
+        };
+
+        *SETTINGS_NOTICE.lock().unwrap() = notice;
+        settings
+    }
+
+    // 以前はカレントディレクトリに保存していたので、設定フォルダに無ければコピーしてくる
+    fn import_legacy_file() {
+        let path = config_path::settings_path();
+        let legacy_path = config_path::legacy_settings_path();
+        if path == legacy_path || path.exists() || !legacy_path.exists() {
+            return;
+        }
+        if let Err(e) = path.parent().map_or(Ok(()), fs::create_dir_all) {
+            eprintln!("設定フォルダ作成エラー: {}", e);
+            return;
+        }
+        if let Err(e) = fs::copy(legacy_path, path) {
+            eprintln!("設定の移動エラー: {}", e);
+        }
+    }
+
+    // 読み込んで最新の形式に変換する（元のバージョン, 設定）。ファイルが無ければNone
+    fn read_and_migrate() -> Result<Option<(u32, Self)>, String> {
+        let Some((version, mut raw)) = Self::read_file()? else {
+            return Ok(None);
+        };
+        if version > SETTINGS_VERSION {
+            return Err(format!("unsupported version {}", version));
+        }
+        Self::migrate(&mut raw, version);
+        Ok(Some((version, Self::parse_raw(&raw)?)))
+    }
+
+    // 型付きで読まずに、トップレベルの項目ごとに分けて読む
+    fn read_file() -> Result<Option<(u32, RawSettings)>, String> {
+        let path = config_path::settings_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let json = RawJson::parse(&content).map_err(|e| e.to_string())?;
+        let mut raw = raw_members(json.value())?;
+
+        // versionがない場合はv0として扱う
+        let version = match raw.remove("version") {
+            Some(version) => version
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid version: {}", version))?,
+            None => 0,
+        };
+        Ok(Some((version, raw)))
+    }
+
+    fn parse_raw(raw: &RawSettings) -> Result<Self, String> {
+        raw_object_text(raw)
+            .parse::<Json<AppSettings>>()
+            .map(|json| json.0)
+            .map_err(|e| e.to_string())
+    }
+
+    fn migrate(raw: &mut RawSettings, from_version: u32) {
+        for migration in &MIGRATIONS[from_version as usize..] {
+            migration(raw);
+        }
+    }
+
+    fn backup_file() -> Option<String> {
+        let path = config_path::settings_path();
+        let backup_path = format!("{}.{}.bak", path.display(), Local::now().format("%Y%m%d-%H%M%S"));
+        match fs::copy(path, &backup_path) {
+            Ok(_) => Some(backup_path),
+            Err(e) => {
+                eprintln!("設定のバックアップエラー: {}", e);
+                None
+            }
         }
     }
 
@@ -152,19 +597,31 @@ impl AppSettings {
         })
         .to_string();
 
-        fs::write("settings.json", json_content)?;
+        let path = config_path::settings_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, json_content)?;
         Ok(())
     }
 }
 
 #[component]
 pub fn SettingsPage(on_navigate_to_recording: EventHandler<()>) -> Element {
+    // 読み込めなかったときは既定値を表示して、保存すると上書きされることを知らせる
+    let load_error = use_hook(|| AppSettings::try_load().err());
     let mut settings = use_signal(|| AppSettings::load());
     let mut save_message = use_signal(|| String::new());
+    let output_devices = use_signal(crate::device_config::list_output_devices);
     let mut i18n = i18n();
+    let active_overrides = overrides::active_keys();
 
-    // 言語が変更されたら、i18nの言語も更新
+    // 言語が変更されたら、i18nの言語も更新（--language などで上書きされている間は上書きが優先）
+    let language_overridden = active_overrides.contains(&"language");
     use_effect(move || {
+        if language_overridden {
+            return;
+        }
         let current_language = settings.read().language;
         match current_language {
             Language::Japanese => i18n.set_language("ja".parse().unwrap_or_default()),
@@ -196,6 +653,25 @@ pub fn SettingsPage(on_navigate_to_recording: EventHandler<()>) -> Element {
                     "{t!(\"settings_title\")}"
                 }
 
+                // コマンドライン引数・環境変数で上書きされている項目
+                if !active_overrides.is_empty() {
+                    rect { height: "10" }
+                    label {
+                        color: "rgb(255, 200, 100)",
+                        font_size: "14",
+                        {t!("overrides_active", keys: active_overrides.join(", "))}
+                    }
+                }
+
+                if let Some(error) = load_error.clone() {
+                    rect { height: "10" }
+                    label {
+                        color: "rgb(255, 200, 100)",
+                        font_size: "14",
+                        {t!("settings_unreadable", error: error)}
+                    }
+                }
+
                 rect { height: "30" }
 
                 // 音声フォーマット設定
@@ -524,6 +1000,312 @@ pub fn SettingsPage(on_navigate_to_recording: EventHandler<()>) -> Element {
                 //     }
                 }
 
+                // イベント検出設定
+                rect {
+                    width: "100%",
+                    height: "auto",
+                    direction: "vertical",
+                    background: "rgb(60, 64, 72)",
+                    border: "1 solid rgb(100, 100, 100)",
+                    corner_radius: "8",
+                    padding: "20",
+                    margin: "10 0",
+
+                    label {
+                        color: "white",
+                        font_size: "20",
+                        "{t!(\"event_capture_section\")}"
+                    }
+
+                    rect { height: "15" }
+
+                    rect {
+                        direction: "horizontal",
+                        cross_align: "center",
+
+                        label {
+                            color: "white",
+                            font_size: "16",
+                            width: "120",
+                            "{t!(\"event_capture\")}: "
+                        }
+
+                        rect {
+                            background: if settings.read().event_capture_enabled { "rgb(0, 120, 255)" } else { "rgb(80, 80, 80)" },
+                            padding: "8",
+                            corner_radius: "4",
+
+                            Button {
+                                onpress: move |_| {
+                                    let current_state = settings.read().event_capture_enabled;
+                                    settings.write().event_capture_enabled = !current_state;
+                                },
+                                label {
+                                    if settings.read().event_capture_enabled { "{t!(\"enabled\")}" } else { "{t!(\"disabled\")}" }
+                                }
+                            }
+                        }
+                    }
+
+                    if settings.read().event_capture_enabled {
+                        rect { height: "15" }
+
+                        rect {
+                            direction: "horizontal",
+                            cross_align: "center",
+
+                            label {
+                                color: "white",
+                                font_size: "16",
+                                width: "120",
+                                "{t!(\"threshold\")}: "
+                            }
+
+                            Dropdown {
+                                value: format!("{}", settings.read().event_threshold_db),
+
+                                for db in [-10.0f32, -20.0, -30.0, -40.0] {
+                                    DropdownItem {
+                                        value: format!("{}", db),
+                                        onpress: move |_| {
+                                            settings.write().event_threshold_db = db;
+                                        },
+                                        label { "{db} dB" }
+                                    }
+                                }
+                            }
+                        }
+
+                        rect { height: "15" }
+
+                        rect {
+                            direction: "horizontal",
+                            cross_align: "center",
+
+                            label {
+                                color: "white",
+                                font_size: "16",
+                                width: "120",
+                                "{t!(\"event_min_duration\")}: "
+                            }
+
+                            Dropdown {
+                                value: format!("{}", settings.read().event_min_duration_ms),
+
+                                for ms in [100u32, 200, 500, 1000] {
+                                    DropdownItem {
+                                        value: format!("{}", ms),
+                                        onpress: move |_| {
+                                            settings.write().event_min_duration_ms = ms;
+                                        },
+                                        label { "{ms} ms" }
+                                    }
+                                }
+                            }
+                        }
+
+                        rect { height: "15" }
+
+                        rect {
+                            direction: "horizontal",
+                            cross_align: "center",
+
+                            label {
+                                color: "white",
+                                font_size: "16",
+                                width: "120",
+                                "{t!(\"event_padding\")}: "
+                            }
+
+                            Dropdown {
+                                value: format!("{}", settings.read().event_padding_ms),
+
+                                for ms in [1000u32, 2000, 5000, 10000] {
+                                    DropdownItem {
+                                        value: format!("{}", ms),
+                                        onpress: move |_| {
+                                            settings.write().event_padding_ms = ms;
+                                        },
+                                        label { "{ms / 1000} s" }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+
+                // モニター出力設定
+                rect {
+                    width: "100%",
+                    height: "auto",
+                    direction: "vertical",
+                    background: "rgb(60, 64, 72)",
+                    border: "1 solid rgb(100, 100, 100)",
+                    corner_radius: "8",
+                    padding: "20",
+                    margin: "10 0",
+
+                    label {
+                        color: "white",
+                        font_size: "20",
+                        "{t!(\"monitor_section\")}"
+                    }
+
+                    rect { height: "15" }
+
+                    rect {
+                        direction: "horizontal",
+                        cross_align: "center",
+
+                        label {
+                            color: "white",
+                            font_size: "16",
+                            width: "120",
+                            "{t!(\"monitor_output\")}: "
+                        }
+
+                        rect {
+                            background: if settings.read().monitor_enabled { "rgb(0, 120, 255)" } else { "rgb(80, 80, 80)" },
+                            padding: "8",
+                            corner_radius: "4",
+
+                            Button {
+                                onpress: move |_| {
+                                    let current_state = settings.read().monitor_enabled;
+                                    settings.write().monitor_enabled = !current_state;
+                                },
+                                label {
+                                    if settings.read().monitor_enabled { "{t!(\"enabled\")}" } else { "{t!(\"disabled\")}" }
+                                }
+                            }
+                        }
+                    }
+
+                    if settings.read().monitor_enabled {
+                        rect { height: "15" }
+
+                        rect {
+                            direction: "horizontal",
+                            cross_align: "center",
+
+                            label {
+                                color: "white",
+                                font_size: "16",
+                                width: "120",
+                                "{t!(\"monitor_delay\")}: "
+                            }
+
+                            Dropdown {
+                                value: format!("{}", settings.read().monitor_delay_ms),
+
+                                for ms in [0u32, 3000, 5000, 7000, 10000, 20000] {
+                                    DropdownItem {
+                                        value: format!("{}", ms),
+                                        onpress: move |_| {
+                                            settings.write().monitor_delay_ms = ms;
+                                        },
+                                        label { "{ms / 1000} s" }
+                                    }
+                                }
+                            }
+                        }
+
+                        rect { height: "15" }
+
+                        rect {
+                            direction: "horizontal",
+                            cross_align: "center",
+
+                            label {
+                                color: "white",
+                                font_size: "16",
+                                width: "120",
+                                "{t!(\"monitor_device\")}: "
+                            }
+
+                            Dropdown {
+                                value: settings.read().monitor_device.clone(),
+
+                                DropdownItem {
+                                    value: String::new(),
+                                    onpress: move |_| {
+                                        settings.write().monitor_device = String::new();
+                                    },
+                                    label { "{t!(\"default_device\")}" }
+                                }
+                                for name in output_devices.read().iter().cloned() {
+                                    DropdownItem {
+                                        value: name.clone(),
+                                        onpress: {
+                                            let name = name.clone();
+                                            move |_| settings.write().monitor_device = name.clone()
+                                        },
+                                        label { "{name}" }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+
+                // メトリクス設定
+                rect {
+                    width: "100%",
+                    height: "auto",
+                    direction: "vertical",
+                    background: "rgb(60, 64, 72)",
+                    border: "1 solid rgb(100, 100, 100)",
+                    corner_radius: "8",
+                    padding: "20",
+                    margin: "10 0",
+
+                    label {
+                        color: "white",
+                        font_size: "20",
+                        "{t!(\"metrics_section\")}"
+                    }
+
+                    rect { height: "15" }
+
+                    rect {
+                        direction: "horizontal",
+                        cross_align: "center",
+
+                        label {
+                            color: "white",
+                            font_size: "16",
+                            width: "120",
+                            "{t!(\"metrics_endpoint\")}: "
+                        }
+
+                        rect {
+                            background: if settings.read().metrics_enabled { "rgb(0, 120, 255)" } else { "rgb(80, 80, 80)" },
+                            padding: "8",
+                            corner_radius: "4",
+
+                            Button {
+                                onpress: move |_| {
+                                    let current_state = settings.read().metrics_enabled;
+                                    settings.write().metrics_enabled = !current_state;
+                                },
+                                label {
+                                    if settings.read().metrics_enabled { "{t!(\"enabled\")}" } else { "{t!(\"disabled\")}" }
+                                }
+                            }
+                        }
+                    }
+
+                    if settings.read().metrics_enabled {
+                        rect { height: "10" }
+
+                        label {
+                            color: "rgb(180, 180, 180)",
+                            font_size: "14",
+                            "http://127.0.0.1:{settings.read().metrics_port}/metrics ({t!(\"restart_required\")})"
+                        }
+                    }
+                }
+
                 rect { height: "20" }
             }
         }
@@ -0,0 +1,212 @@
+//OSからの終了要求（SIGTERM、コンソールを閉じる、ログオフ・シャットダウンなど）で録音を最後まで保存する
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// 終了要求を受けてから、この時間を過ぎても書き出しが終わらなければ急がせる。
+/// FLACは最後にまとめて書き出すので、途中で終了すると録音全体が失われる。そのため、書き出しが終わるまでは待ち続ける
+const HURRY_AFTER: Duration = Duration::from_secs(20);
+
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+static SHUTDOWN_HURRIED: AtomicBool = AtomicBool::new(false);
+static ACTIVE_RECORDINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// 終了要求が来ているか。録音スレッドは停止フラグと同様に確認する
+pub fn is_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::Relaxed)
+}
+
+/// すぐに強制終了させられる終了要求か。時間のかかる書き出し（FLACのエンコード）は避ける
+pub fn is_hurried() -> bool {
+    SHUTDOWN_HURRIED.load(Ordering::Relaxed)
+}
+
+/// 録音中のファイルがあるか
+pub fn has_active_recordings() -> bool {
+    ACTIVE_RECORDINGS.load(Ordering::SeqCst) > 0
+}
+
+/// 録音スレッドが生きている間保持する。ファイルを閉じ終わるまでdropしないこと
+pub struct RecordingGuard(());
+
+/// 録音スレッドの開始時に呼ぶ
+pub fn begin_recording() -> RecordingGuard {
+    ACTIVE_RECORDINGS.fetch_add(1, Ordering::SeqCst);
+    RecordingGuard(())
+}
+
+impl Drop for RecordingGuard {
+    fn drop(&mut self) {
+        ACTIVE_RECORDINGS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// 全ての録音を止めて、ファイルの書き出しが終わるまで待つ
+pub fn shutdown_and_wait() {
+    wait_for_recordings(HURRY_AFTER);
+}
+
+// 強制終了までの時間が短いとき（コンソールを閉じた・ログオフなど）は、最初から書き出しを急がせる
+#[cfg(windows)]
+fn shutdown_hurried_and_wait() {
+    wait_for_recordings(Duration::ZERO);
+}
+
+// 録音スレッドが全てファイルを閉じる（RecordingGuardをdropする）まで戻らない
+fn wait_for_recordings(hurry_after: Duration) {
+    SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+
+    let started = Instant::now();
+    while has_active_recordings() {
+        if !is_hurried() && started.elapsed() >= hurry_after {
+            if !hurry_after.is_zero() {
+                eprintln!("録音ファイルの書き出しに時間がかかっています。書き終わるまで待ちます");
+            }
+            // まだ書き出していないFLACは、すぐ書き終わるWAVで保存させる
+            SHUTDOWN_HURRIED.store(true, Ordering::SeqCst);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// OSの終了シグナルのハンドラを登録する
+pub fn install_handlers() {
+    platform::install();
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    static SIGNAL_RECEIVED: AtomicBool = AtomicBool::new(false);
+
+    // シグナルハンドラ内ではフラグを立てるだけにする
+    extern "C" fn handle_signal(_signal: libc::c_int) {
+        SIGNAL_RECEIVED.store(true, Ordering::SeqCst);
+    }
+
+    pub fn install() {
+        for signal in [libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+            unsafe {
+                libc::signal(signal, handle_signal as *const () as libc::sighandler_t);
+            }
+        }
+
+        thread::spawn(|| {
+            while !SIGNAL_RECEIVED.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(100));
+            }
+            super::shutdown_and_wait();
+            std::process::exit(0);
+        });
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ptr::null_mut;
+    use std::thread;
+    use windows_sys::Win32::Foundation::{BOOL, FALSE, HWND, LPARAM, LRESULT, TRUE, WPARAM};
+    use windows_sys::Win32::System::Console::{CTRL_BREAK_EVENT, CTRL_C_EVENT, SetConsoleCtrlHandler};
+    use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows_sys::Win32::System::Shutdown::{ShutdownBlockReasonCreate, ShutdownBlockReasonDestroy};
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, MSG, RegisterClassW,
+        TranslateMessage, WM_ENDSESSION, WM_QUERYENDSESSION, WNDCLASSW,
+    };
+
+    // コンソール（--daemon / --ctl をコンソールから起動したとき）のCtrl+C、閉じる、ログオフ、シャットダウン。
+    // ハンドラは別スレッドで呼ばれ、戻るとプロセスが終了させられるので、書き出しが終わるまでここで待つ。
+    // Ctrl+C以外は数秒で強制終了させられるので、その時間内に終わる書き出し方にする
+    unsafe extern "system" fn handle_ctrl(ctrl_type: u32) -> BOOL {
+        if ctrl_type == CTRL_C_EVENT || ctrl_type == CTRL_BREAK_EVENT {
+            super::shutdown_and_wait();
+        } else {
+            super::shutdown_hurried_and_wait();
+        }
+        std::process::exit(0);
+    }
+
+    // GUIのプロセスにはコンソールのイベントが来ないので、ログオフ・シャットダウンは
+    // 隠しウィンドウで受け取る。録音中は終了を待ってもらう理由を出して、書き出しが終わるまで待つ
+    unsafe extern "system" fn window_proc(hwnd: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        match message {
+            WM_QUERYENDSESSION => {
+                if super::has_active_recordings() {
+                    let reason = wide("録音ファイルを保存しています");
+                    unsafe { ShutdownBlockReasonCreate(hwnd, reason.as_ptr()) };
+                }
+                TRUE as LRESULT
+            }
+            WM_ENDSESSION => {
+                if wparam != 0 {
+                    super::shutdown_and_wait();
+                    unsafe { ShutdownBlockReasonDestroy(hwnd) };
+                    std::process::exit(0);
+                }
+                // 終了が取り消された
+                unsafe { ShutdownBlockReasonDestroy(hwnd) };
+                0
+            }
+            _ => unsafe { DefWindowProcW(hwnd, message, wparam, lparam) },
+        }
+    }
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    pub fn install() {
+        unsafe {
+            if SetConsoleCtrlHandler(Some(handle_ctrl), TRUE) == FALSE {
+                eprintln!("終了ハンドラの登録に失敗しました");
+            }
+        }
+
+        thread::spawn(|| unsafe {
+            let class_name = wide("rokuon-kun-session");
+            let instance = GetModuleHandleW(std::ptr::null());
+            let mut class: WNDCLASSW = std::mem::zeroed();
+            class.lpfnWndProc = Some(window_proc);
+            class.hInstance = instance;
+            class.lpszClassName = class_name.as_ptr();
+            if RegisterClassW(&class) == 0 {
+                eprintln!("終了通知用ウィンドウの登録に失敗しました");
+                return;
+            }
+
+            // 表示はしない。メッセージ専用ウィンドウにはログオフの通知が来ないので通常のウィンドウにする
+            let hwnd = CreateWindowExW(
+                0,
+                class_name.as_ptr(),
+                class_name.as_ptr(),
+                0,
+                0,
+                0,
+                0,
+                0,
+                null_mut(),
+                null_mut(),
+                instance,
+                std::ptr::null(),
+            );
+            if hwnd.is_null() {
+                eprintln!("終了通知用ウィンドウの作成に失敗しました");
+                return;
+            }
+
+            let mut message: MSG = std::mem::zeroed();
+            while GetMessageW(&mut message, null_mut(), 0, 0) > 0 {
+                TranslateMessage(&message);
+                DispatchMessageW(&message);
+            }
+        });
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    pub fn install() {}
+}
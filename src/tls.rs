@@ -0,0 +1,120 @@
+//バックグラウンド録音の制御通信を暗号化する（127.0.0.1 以外で待ち受けるときに使う）
+//
+// 証明書と秘密鍵はPEMファイルで指定する。自己署名の証明書でよく、
+// 操作する側（--ctl）は同じ証明書ファイルを持っていて、サーバーがその証明書を出したときだけ接続する（ピン留め）
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{self, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{
+    CertificateError, ClientConfig, ClientConnection, DigitallySignedStruct, ServerConfig, ServerConnection,
+    SignatureScheme, StreamOwned,
+};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, TcpStream};
+use std::path::Path;
+use std::sync::Arc;
+
+/// 暗号化している・していない接続をまとめて扱う
+pub trait Connection: Read + Write {}
+
+impl<T: Read + Write> Connection for T {}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(crypto::ring::default_provider())
+}
+
+fn load_certificates(cert_path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let certificates = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| io::Error::other(format!("証明書を読み込めません ({}): {}", cert_path.display(), e)))?;
+    if certificates.is_empty() {
+        return Err(io::Error::other(format!("証明書がありません: {}", cert_path.display())));
+    }
+    Ok(certificates)
+}
+
+/// 待ち受ける側の設定
+pub fn server_config(cert_path: &Path, key_path: &Path) -> io::Result<Arc<ServerConfig>> {
+    let certificates = load_certificates(cert_path)?;
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| io::Error::other(format!("秘密鍵を読み込めません ({}): {}", key_path.display(), e)))?;
+    let config = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_no_client_auth()
+        .with_single_cert(certificates, key)
+        .map_err(io::Error::other)?;
+    Ok(Arc::new(config))
+}
+
+/// 接続する側の設定。`cert_path` の先頭の証明書を出したサーバーだけを信頼する
+pub fn client_config(cert_path: &Path) -> io::Result<Arc<ClientConfig>> {
+    let certificate = load_certificates(cert_path)?.remove(0);
+    let provider = provider();
+    let config = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(PinnedCertificate { certificate, provider }))
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// 受け付けた接続を暗号化する（ハンドシェイクは最初の読み書きで行う）
+pub fn accept(config: &Arc<ServerConfig>, stream: TcpStream) -> io::Result<Box<dyn Connection>> {
+    let connection = ServerConnection::new(config.clone()).map_err(io::Error::other)?;
+    Ok(Box::new(StreamOwned::new(connection, stream)))
+}
+
+/// 接続を暗号化する
+pub fn connect(config: &Arc<ClientConfig>, address: IpAddr, stream: TcpStream) -> io::Result<Box<dyn Connection>> {
+    let connection = ClientConnection::new(config.clone(), ServerName::from(address)).map_err(io::Error::other)?;
+    Ok(Box::new(StreamOwned::new(connection, stream)))
+}
+
+// 自己署名の証明書を名前ではなく中身で確かめる
+#[derive(Debug)]
+struct PinnedCertificate {
+    certificate: CertificateDer<'static>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if end_entity.as_ref() == self.certificate.as_ref() {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(CertificateError::UnknownIssuer))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}